@@ -34,6 +34,7 @@ enum class InternalFileHandleType(
     MESSAGE_LOGGER("message_logger", "message_logger.db", isDatabase = true),
     PINNED_BEST_FRIEND("pinned_best_friend", "pinned_best_friend.txt"),
     NATIVE_SIG_CACHE("native_sig_cache", "native_sig_cache.txt"),
+    NATIVE_SIG_DEFINITIONS("native_sig_definitions", "native_sig_definitions.json"),
     SIF("sif", "libsif.so");
 
     fun resolve(context: Context): File = if (isDatabase) {
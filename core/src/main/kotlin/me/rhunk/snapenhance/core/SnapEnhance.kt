@@ -203,6 +203,16 @@ class SnapEnhance {
                 appContext.log.verbose("old signature cache $it")
             }
 
+        appContext.native.signatureDefinitions = appContext.fileHandlerManager.getFileHandle(FileHandleScope.INTERNAL.key, InternalFileHandleType.NATIVE_SIG_DEFINITIONS.key)
+            .toWrapper()
+            .readBytes()
+            .takeIf { it.isNotEmpty() }
+            ?.toString(Charsets.UTF_8)
+
+        appContext.native.clientVersion = runCatching {
+            appContext.androidContext.packageManager.getPackageInfo(appContext.androidContext.packageName, 0).versionName
+        }.getOrNull()
+
         val lateInit = appContext.native.initOnce {
             nativeUnaryCallCallback = { request ->
                 appContext.event.post(NativeUnaryCallEvent(request.uri, request.buffer)) {
@@ -210,14 +220,20 @@ class SnapEnhance {
                     request.canceled = canceled
                 }
             }
+            signatureCacheCallback = { signatureCache ->
+                signatureCache.takeIf { it != oldSignatureCache }?.let {
+                    appContext.log.verbose("new signature cache $it")
+                    nativeSigCacheFileHandle.writeBytes(it.toByteArray(Charsets.UTF_8))
+                }
+                appContext.log.verbose("native hook status ${appContext.native.getHookStatus()}")
+            }
             appContext.reloadNativeConfig()
         }.let { init ->
             {
                 init()
-                appContext.native.signatureCache.takeIf { it != oldSignatureCache }?.let {
-                    appContext.log.verbose("new signature cache $it")
-                    nativeSigCacheFileHandle.writeBytes(it.toByteArray(Charsets.UTF_8))
-                }
+                appContext.log.verbose("native signature report ${appContext.native.getSignatureReport()}")
+                appContext.log.verbose("native hook status ${appContext.native.getHookStatus()}")
+                appContext.log.verbose("native hooks ${appContext.native.getHooks()}")
             }
         }
 
@@ -0,0 +1,93 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use snapenhance::sig::{scanner, signature::Signature};
+
+const BUFFER_SIZE: usize = 64 * 1024 * 1024;
+const PATTERNS: [&str; 3] = [
+    "FF FF 00 A9 3F 00 00 F9",
+    "A8 03 1F F8 C2 00 00 94",
+    "00 E4 00 6F 29 00 80 52 ?? 00 04 8B",
+];
+
+// previous byte-by-byte implementation, kept as a baseline
+fn naive_scan(haystack: &[u8], pattern: &str) -> Vec<usize> {
+    let mut bytes = Vec::new();
+    let mut mask = Vec::new();
+
+    for token in pattern.split(' ') {
+        if token.starts_with('?') {
+            bytes.push(0);
+            mask.push(false);
+        } else {
+            bytes.push(u8::from_str_radix(token, 16).unwrap());
+            mask.push(true);
+        }
+    }
+
+    (0..=haystack.len() - bytes.len()).filter(|&i| {
+        (0..bytes.len()).all(|j| !mask[j] || haystack[i + j] == bytes[j])
+    }).collect()
+}
+
+fn synthetic_buffer() -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    rng.fill(buffer.as_mut_slice());
+
+    // plant every pattern near the end so the whole buffer is walked
+    for (i, pattern) in PATTERNS.iter().enumerate() {
+        let offset = BUFFER_SIZE - 0x1000 * (i + 1);
+        for (j, token) in pattern.split(' ').enumerate() {
+            if let Ok(byte) = u8::from_str_radix(token, 16) {
+                buffer[offset + j] = byte;
+            }
+        }
+    }
+
+    buffer
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let buffer = synthetic_buffer();
+
+    for pattern in PATTERNS {
+        assert_eq!(scanner::scan(&buffer, &pattern.parse::<Signature>().unwrap(), false), naive_scan(&buffer, pattern));
+    }
+
+    let mut group = c.benchmark_group("signature_scan");
+    group.throughput(Throughput::Bytes(BUFFER_SIZE as u64));
+    group.sample_size(10);
+
+    for pattern in PATTERNS {
+        let parsed = pattern.parse::<Signature>().unwrap();
+        group.bench_function(format!("anchored/{}", pattern), |b| {
+            b.iter(|| scanner::scan(black_box(&buffer), &parsed, false))
+        });
+        group.bench_function(format!("naive/{}", pattern), |b| {
+            b.iter(|| naive_scan(black_box(&buffer), pattern))
+        });
+    }
+
+    let parsed = PATTERNS.iter().map(|pattern| pattern.parse::<Signature>().unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(
+        scanner::scan_many(&buffer, &parsed, false),
+        parsed.iter().map(|pattern| scanner::scan(&buffer, pattern, false)).collect::<Vec<_>>()
+    );
+    assert_eq!(scanner::scan_parallel(&[&buffer], &parsed, None).unwrap()[0], scanner::scan_many(&buffer, &parsed, false));
+
+    group.bench_function("batch/all", |b| {
+        b.iter(|| scanner::scan_many(black_box(&buffer), &parsed, false))
+    });
+    group.bench_function("parallel/all", |b| {
+        b.iter(|| scanner::scan_parallel(&[black_box(&buffer)], &parsed, None))
+    });
+    group.bench_function("sequential/all", |b| {
+        b.iter(|| parsed.iter().map(|pattern| scanner::scan(black_box(&buffer), pattern, false)).collect::<Vec<_>>())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
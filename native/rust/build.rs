@@ -1,3 +1,6 @@
 fn main() {
-    println!("cargo:rustc-link-lib=static=c++");
-}
\ No newline at end of file
+    // the android toolchain expects the c++ runtime dobby depends on to be linked statically
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("android") {
+        println!("cargo:rustc-link-lib=static=c++");
+    }
+}
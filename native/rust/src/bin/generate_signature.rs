@@ -0,0 +1,88 @@
+// derives the shortest unique signature of a function from its virtual address, optionally stable across other builds
+use std::{error::Error, path::PathBuf, process::ExitCode};
+
+use snapenhance::{offline::OfflineModule, sig::generator::{generate, GeneratorOptions, Target}};
+
+const USAGE: &str = "usage: generate_signature <libclient.so | split_config.apk> <address> [--against <file> <address>]... [--library <name>] [--max-start <bytes>] [--max-length <bytes>]";
+
+struct Options {
+    inputs: Vec<(PathBuf, usize)>,
+    library: String,
+    generator: GeneratorOptions,
+}
+
+fn parse_number(value: &str) -> Result<usize, Box<dyn Error>> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number {}", value).into())
+}
+
+fn parse_options() -> Result<Options, Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let mut inputs = Vec::new();
+    let mut positional = Vec::new();
+    let mut library = "libclient.so".to_string();
+    let mut generator = GeneratorOptions::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--against" => {
+                let path = args.next().ok_or("--against expects a file and an address")?;
+                let address = args.next().ok_or("--against expects a file and an address")?;
+                inputs.push((PathBuf::from(path), parse_number(&address)?));
+            }
+            "--library" => library = args.next().ok_or("--library expects a file name")?,
+            "--max-start" => generator.max_start = parse_number(&args.next().ok_or("--max-start expects a size")?)?,
+            "--max-length" => generator.max_length = parse_number(&args.next().ok_or("--max-length expects a size")?)?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg).into()),
+            _ => positional.push(arg),
+        }
+    }
+
+    let [path, address] = positional.as_slice() else {
+        return Err("expected an input file and a function address".into());
+    };
+    inputs.insert(0, (PathBuf::from(path), parse_number(address)?));
+
+    Ok(Options { inputs, library, generator })
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let modules = options.inputs.iter()
+        .map(|(path, _)| OfflineModule::load(path, &options.library))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let arch = modules[0].arch.ok_or("unsupported ELF machine")?;
+    if modules.iter().any(|module| module.arch != Some(arch)) {
+        return Err("all the builds must target the same architecture".into());
+    }
+
+    let targets = modules.iter().zip(&options.inputs)
+        .map(|(module, (_, address))| Target::from_mapped_lib(&module.mapped_lib, module.base + address))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let generated = generate(arch, &targets, options.generator)?;
+    eprintln!("{} bytes, unique in {} build(s)", generated.signature.len(), targets.len());
+    println!("{}", generated.to_candidate());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
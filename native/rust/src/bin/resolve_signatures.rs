@@ -0,0 +1,117 @@
+// resolves the built-in signatures in a libclient.so or split apk on the host, the cache is printed on stdout
+use std::{error::Error, path::PathBuf, process::ExitCode};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use snapenhance::{builtin_signatures, offline::OfflineModule, sig};
+
+const USAGE: &str = "usage: resolve_signatures <libclient.so | split_config.apk> [--library <name>] [--client-version <version>] [--definitions <file.json>] [--verbose]";
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        eprintln!("[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+struct Options {
+    path: PathBuf,
+    library: String,
+    client_version: Option<String>,
+    definitions: Option<PathBuf>,
+    verbose: bool,
+}
+
+fn parse_options() -> Result<Options, Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut library = "libclient.so".to_string();
+    let mut client_version = None;
+    let mut definitions = None;
+    let mut verbose = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--library" => library = args.next().ok_or("--library expects a file name")?,
+            "--client-version" => client_version = Some(args.next().ok_or("--client-version expects a version")?),
+            "--definitions" => definitions = Some(PathBuf::from(args.next().ok_or("--definitions expects a file")?)),
+            "--verbose" | "-v" => verbose = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg).into()),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Options {
+        path: path.ok_or("missing input file")?,
+        library,
+        client_version,
+        definitions,
+        verbose,
+    })
+}
+
+fn run(options: Options) -> Result<bool, Box<dyn Error>> {
+    if options.verbose {
+        log::set_logger(&StderrLogger).map_err(|error| error.to_string())?;
+        log::set_max_level(LevelFilter::Debug);
+    }
+
+    let module = OfflineModule::load(&options.path, &options.library)?;
+    sig::definition::set_target_arch(module.arch.ok_or("unsupported ELF machine")?);
+
+    if let Some(client_version) = &options.client_version {
+        sig::definition::set_client_version(client_version);
+    }
+
+    sig::set_cache_module(&module.mapped_lib);
+
+    let signatures = builtin_signatures();
+
+    if let Some(definitions) = &options.definitions {
+        let known_signatures = signatures.iter().map(|signature| signature.name).collect::<Vec<_>>();
+        sig::overrides::load_definitions(&std::fs::read_to_string(definitions)?, &known_signatures).map_err(|errors| {
+            errors.iter().map(|error| error.to_string()).collect::<Vec<_>>().join("\n")
+        })?;
+    }
+    sig::prefetch_signatures(&module.mapped_lib, &signatures);
+
+    let mut resolved_all = true;
+
+    for signature in signatures {
+        match sig::find_signature(&module.mapped_lib, signature) {
+            Some(address) => eprintln!("{} {:#x}", signature.name, address - module.base),
+            None => {
+                eprintln!("{} unresolved", signature.name);
+                resolved_all = false;
+            }
+        }
+    }
+
+    println!("{}", serde_json::to_string(&sig::get_signatures())?);
+    Ok(resolved_all)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,27 +1,9 @@
 use jni::{objects::GlobalRef, JavaVM};
-use once_cell::sync::{Lazy, OnceCell};
-
-use crate::mapped_lib::MappedLib;
+use once_cell::sync::OnceCell;
 
 static NATIVE_LIB_INSTANCE: OnceCell<GlobalRef> = OnceCell::new();
 static JAVA_VM: OnceCell<usize> = OnceCell::new();
 
-pub static CLIENT_MODULE: Lazy<MappedLib> = Lazy::new(|| {
-    let mut client_module = MappedLib::new("libclient.so".into());
-
-    if let Err(error) = client_module.search() {
-        warn!("Unable to find libclient.so: {}", error);
-
-        client_module = MappedLib::new("split_config.arm".into());
-
-        if let Err(error) = client_module.search() {
-            panic!("Unable to find split_config.arm: {}", error);
-        }
-    }
-
-    client_module
-});
-
 
 pub fn set_native_lib_instance(instance: GlobalRef) {
     NATIVE_LIB_INSTANCE.set(instance).expect("NativeLib instance already set");
@@ -1,6 +1,6 @@
-use std::{error::Error, sync::Mutex};
+use std::{error::Error, sync::Mutex, time::Duration};
 use jni::{objects::JObject, JNIEnv};
-use crate::util::get_jni_string;
+use crate::{modules, util::get_jni_string};
 
 static NATIVE_CONFIG: Mutex<Option<NativeConfig>> = Mutex::new(None);
 
@@ -14,9 +14,29 @@ pub(crate) struct NativeConfig {
     pub disable_metrics: bool,
     pub composer_hooks: bool,
     pub custom_emoji_font_path: Option<String>,
+    // overall time allowed to signature scans during init
+    pub signature_scan_budget: Option<Duration>,
+}
+
+// config fields gating the native modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConfigKey {
+    DisableBitmoji,
+    DisableMetrics,
+    ComposerHooks,
+    CustomEmojiFontPath,
 }
 
 impl NativeConfig {
+    pub fn is_enabled(&self, key: ConfigKey) -> bool {
+        match key {
+            ConfigKey::DisableBitmoji => self.disable_bitmoji,
+            ConfigKey::DisableMetrics => self.disable_metrics,
+            ConfigKey::ComposerHooks => self.composer_hooks,
+            ConfigKey::CustomEmojiFontPath => self.custom_emoji_font_path.is_some(),
+        }
+    }
+
     fn new(env: &mut JNIEnv, obj: JObject) -> Result<Self, Box<dyn Error>> {
         macro_rules! get_boolean {
             ($field:expr) => {
@@ -24,6 +44,12 @@ impl NativeConfig {
             };
         }
 
+        macro_rules! get_long {
+            ($field:expr) => {
+                env.get_field(&obj, $field, "J")?.j()?
+            };
+        }
+
         macro_rules! get_string {
             ($field:expr) => {
                 match env.get_field(&obj, $field, "Ljava/lang/String;")?.l()? {
@@ -41,14 +67,22 @@ impl NativeConfig {
             disable_metrics: get_boolean!("disableMetrics"),
             composer_hooks: get_boolean!("composerHooks"),
             custom_emoji_font_path: get_string!("customEmojiFontPath"),
+            signature_scan_budget: match get_long!("signatureScanBudget") {
+                budget if budget > 0 => Some(Duration::from_millis(budget as u64)),
+                _ => None,
+            },
         })
     }
 }
 
+// reloading the config installs or removes the hooks of the modules it toggles
 pub fn load_config(mut env: JNIEnv, _class: JObject, obj: JObject)  {
-    NATIVE_CONFIG.lock().unwrap().replace(
-        NativeConfig::new(&mut env, obj).expect("Failed to load NativeConfig")
-    );
-    
-    info!("Config loaded {:?}", native_config());
+    let config = NativeConfig::new(&mut env, obj).expect("Failed to load NativeConfig");
+    let previous = NATIVE_CONFIG.lock().unwrap().replace(config.clone());
+
+    info!("Config loaded {:?}", config);
+
+    if let Some(previous) = previous {
+        modules::apply_config(&previous, &config);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,199 @@
+use std::{path::Path, sync::{mpsc::{self, Sender}, Mutex}, thread};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::{mapped_lib::MappedLib, target::{self, RetryPolicy, TargetModule}};
+
+type Install = Box<dyn FnOnce(&'static MappedLib) -> Result<(), String> + Send>;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "state", content = "error")]
+pub enum HookState {
+    // waiting for the target module to be loaded
+    Pending,
+    Installed,
+    Failed(String),
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HookStatus {
+    pub name: &'static str,
+    pub module: &'static str,
+    #[serde(flatten)]
+    pub state: HookState,
+}
+
+struct DeferredHook {
+    status: HookStatus,
+    target: TargetModule,
+    install: Option<Install>,
+}
+
+static DEFERRED_HOOKS: Mutex<Vec<DeferredHook>> = Mutex::new(Vec::new());
+
+// the linker hook runs with the linker lock held, installations run on their own thread one target at a time
+static INSTALLER: Lazy<Sender<TargetModule>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel::<TargetModule>();
+
+    thread::Builder::new().name("deferred-hooks".into()).spawn(move || {
+        receiver.into_iter().for_each(|target| install_pending(&target));
+    }).expect("Failed to spawn the deferred hooks thread");
+    sender
+});
+
+/*
+ * runs the installation once the target module is mapped
+ * - soon after if the module is already loaded
+ * - otherwise once the linker hook reports the module as loaded
+ * installations run on the installer thread, in the order they were deferred
+ */
+pub fn defer(name: &'static str, target: &TargetModule, install: impl FnOnce(&'static MappedLib) -> Result<(), String> + Send + 'static) {
+    DEFERRED_HOOKS.lock().unwrap().push(DeferredHook {
+        status: HookStatus { name, module: target.name, state: HookState::Pending },
+        target: *target,
+        install: Some(Box::new(install)),
+    });
+
+    queue(target);
+}
+
+fn queue(target: &TargetModule) {
+    if INSTALLER.send(*target).is_err() {
+        warn!("The deferred hooks thread is gone, {} hooks won't be installed", target.name);
+    }
+}
+
+fn set_state(name: &str, state: HookState) {
+    // the hook may have been cancelled while it was installed
+    if let Some(hook) = DEFERRED_HOOKS.lock().unwrap().iter_mut().find(|hook| hook.status.name == name) {
+        hook.status.state = state;
+    }
+}
+
+// the installations are taken out of the list so they run without holding any lock
+fn install_pending(target: &TargetModule) {
+    let pending = DEFERRED_HOOKS.lock().unwrap().iter_mut()
+        .filter(|hook| hook.target.name == target.name)
+        .filter_map(|hook| hook.install.take().map(|install| (hook.status.name, install)))
+        .collect::<Vec<_>>();
+
+    if pending.is_empty() {
+        return;
+    }
+
+    // the module was just loaded or is being looked up for a new hook, waiting for it is pointless
+    let mapped_lib = match target::find_module(&target.retry(RetryPolicy::once())) {
+        Ok(mapped_lib) => mapped_lib,
+        Err(error) => {
+            debug!("Deferring {} hooks: {}", pending.len(), error);
+            let mut hooks = DEFERRED_HOOKS.lock().unwrap();
+            for (name, install) in pending {
+                if let Some(hook) = hooks.iter_mut().find(|hook| hook.status.name == name) {
+                    hook.install = Some(install);
+                }
+            }
+            return;
+        }
+    };
+
+    for (name, install) in pending {
+        set_state(name, match install(mapped_lib) {
+            Ok(()) => HookState::Installed,
+            Err(error) => {
+                warn!("Failed to install {}: {}", name, error);
+                HookState::Failed(error)
+            }
+        });
+    }
+}
+
+// forgets the hook, its installation won't run if it is still pending
+pub fn cancel(name: &str) {
+    DEFERRED_HOOKS.lock().unwrap().retain(|hook| hook.status.name != name);
+}
+
+// called by the linker hook after a library is loaded, the installation is left to the installer thread
+pub fn on_library_loaded(path: &str) {
+    let Some(file_name) = Path::new(path).file_name().map(|file_name| file_name.to_string_lossy()) else {
+        return;
+    };
+
+    let target = DEFERRED_HOOKS.lock().unwrap().iter()
+        .find(|hook| hook.install.is_some() && hook.target.file_names.contains(&file_name.as_ref()))
+        .map(|hook| hook.target);
+
+    if let Some(target) = target {
+        debug!("{} loaded, installing deferred hooks of {}", path, target.name);
+        queue(&target);
+    }
+}
+
+pub fn hook_status() -> Vec<HookStatus> {
+    DEFERRED_HOOKS.lock().unwrap().iter().map(|hook| hook.status.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{Duration, Instant}};
+
+    use super::*;
+
+    fn state(name: &str) -> Option<HookState> {
+        hook_status().into_iter().find(|status| status.name == name).map(|status| status.state)
+    }
+
+    fn is_installed(name: &str) -> bool {
+        state(name) == Some(HookState::Installed)
+    }
+
+    // the installer runs in order, the hooks queued before a new one are processed once it is installed
+    fn wait_installer(name: &'static str) {
+        defer(name, &TargetModule::new("deferred_libc", &["libc.so.6"]), |_| Ok(()));
+
+        let start = Instant::now();
+        while !is_installed(name) {
+            assert!(start.elapsed() < Duration::from_secs(10), "{} was never installed", name);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn installs_hooks_of_loaded_modules() {
+        let libc = TargetModule::new("deferred_libc", &["libc.so.6"]);
+
+        defer("deferred_installed", &libc, |mapped_lib| {
+            assert!(!mapped_lib.regions.is_empty());
+            Ok(())
+        });
+        defer("deferred_failed", &libc, |_| Err("no signature".to_string()));
+        wait_installer("deferred_installed_done");
+
+        assert_eq!(state("deferred_installed"), Some(HookState::Installed));
+        assert_eq!(state("deferred_failed"), Some(HookState::Failed("no signature".into())));
+        assert!(is_installed("deferred_installed"));
+    }
+
+    #[test]
+    fn waits_for_missing_modules() {
+        let missing = TargetModule::new("deferred_missing", &["libdeferred_missing.so"]);
+        let installs = Arc::new(AtomicUsize::new(0));
+
+        let counter = installs.clone();
+        defer("deferred_pending", &missing, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+
+        on_library_loaded("/data/app/libdeferred_missing.so");
+        on_library_loaded("/data/app/libother.so");
+        wait_installer("deferred_pending_done");
+
+        assert_eq!(state("deferred_pending"), Some(HookState::Pending));
+        assert_eq!(installs.load(Ordering::SeqCst), 0);
+        assert_eq!(serde_json::to_string(&hook_status().iter().find(|status| status.name == "deferred_pending")).unwrap(), r#"{"name":"deferred_pending","module":"deferred_missing","state":"pending"}"#);
+
+        cancel("deferred_pending");
+        assert_eq!(state("deferred_pending"), None);
+    }
+}
@@ -0,0 +1,395 @@
+use crate::memory::{page_start, read_u16, read_u32, read_u64};
+
+pub const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_NOTE: u32 = 4;
+
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+pub const EM_386: u16 = 3;
+pub const EM_ARM: u16 = 40;
+pub const EM_X86_64: u16 = 62;
+pub const EM_AARCH64: u16 = 183;
+
+const NT_GNU_BUILD_ID: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_STRSZ: u64 = 10;
+const DT_GNU_HASH: u64 = 0x6ffffef5;
+
+const SHN_UNDEF: u16 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+}
+
+fn read_word(image: &[u8], offset: usize, class64: bool) -> Option<u64> {
+    if class64 {
+        read_u64(image, offset)
+    } else {
+        read_u32(image, offset).map(|value| value as u64)
+    }
+}
+
+pub fn is_elf(image: &[u8]) -> bool {
+    image.starts_with(ELF_MAGIC)
+}
+
+pub fn machine(image: &[u8]) -> Option<u16> {
+    is_elf(image).then(|| read_u16(image, 0x12)).flatten()
+}
+
+// parses the program headers of a little endian ELF image starting with its header
+pub fn program_headers(image: &[u8]) -> Option<Vec<ProgramHeader>> {
+    if !is_elf(image) {
+        return None;
+    }
+
+    let class = *image.get(4)?;
+    let (phoff, phentsize, phnum) = match class {
+        ELFCLASS64 => (read_u64(image, 0x20)? as usize, read_u16(image, 0x36)? as usize, read_u16(image, 0x38)? as usize),
+        ELFCLASS32 => (read_u32(image, 0x1C)? as usize, read_u16(image, 0x2A)? as usize, read_u16(image, 0x2C)? as usize),
+        _ => return None,
+    };
+
+    (0..phnum).map(|index| {
+        let offset = phoff + index * phentsize;
+
+        Some(if class == ELFCLASS64 {
+            ProgramHeader {
+                p_type: read_u32(image, offset)?,
+                p_flags: read_u32(image, offset + 0x4)?,
+                p_offset: read_u64(image, offset + 0x8)?,
+                p_vaddr: read_u64(image, offset + 0x10)?,
+                p_filesz: read_u64(image, offset + 0x20)?,
+                p_memsz: read_u64(image, offset + 0x28)?,
+            }
+        } else {
+            ProgramHeader {
+                p_type: read_u32(image, offset)?,
+                p_offset: read_u32(image, offset + 0x4)? as u64,
+                p_vaddr: read_u32(image, offset + 0x8)? as u64,
+                p_filesz: read_u32(image, offset + 0x10)? as u64,
+                p_memsz: read_u32(image, offset + 0x14)? as u64,
+                p_flags: read_u32(image, offset + 0x18)?,
+            }
+        })
+    }).collect()
+}
+
+// build-id stored in a PT_NOTE segment
+fn find_build_id(notes: &[u8]) -> Option<Vec<u8>> {
+    let mut offset = 0usize;
+
+    // the sizes come from the file, a malformed note must not overflow them
+    while offset.saturating_add(12) <= notes.len() {
+        let name_size = read_u32(notes, offset)? as usize;
+        let desc_size = read_u32(notes, offset + 4)? as usize;
+        let note_type = read_u32(notes, offset + 8)?;
+        let name_start = offset + 12;
+        let desc_start = name_start.checked_add(name_size.checked_next_multiple_of(4)?)?;
+
+        if note_type == NT_GNU_BUILD_ID && notes.get(name_start..name_start.checked_add(name_size)?)? == b"GNU\0" {
+            return notes.get(desc_start..desc_start.checked_add(desc_size)?).map(|desc| desc.to_vec());
+        }
+
+        offset = desc_start.checked_add(desc_size.checked_next_multiple_of(4)?)?;
+    }
+
+    None
+}
+
+// read access to the memory of a loaded image by virtual address, None for unmapped or unreadable ranges
+pub trait VirtualMemory {
+    fn read(&self, vaddr: u64, length: usize) -> Option<&[u8]>;
+}
+
+// image laid out at its virtual addresses, such as a library without gaps between its segments
+impl VirtualMemory for [u8] {
+    fn read(&self, vaddr: u64, length: usize) -> Option<&[u8]> {
+        self.get(vaddr as usize..vaddr as usize + length)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DynamicEntry {
+    pub tag: u64,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    // virtual address of the symbol
+    pub value: u64,
+    pub size: u64,
+}
+
+/*
+ * ELF image mapped by a loader
+ * - the header and program headers are read from the first page of the file
+ * - every other structure is located through its virtual address
+ */
+pub struct LoadedImage<'a, M: VirtualMemory + ?Sized> {
+    memory: &'a M,
+    class64: bool,
+    // difference between the load address and the virtual address of the image
+    load_bias: u64,
+    pub program_headers: Vec<ProgramHeader>,
+}
+
+impl<'a, M: VirtualMemory + ?Sized> LoadedImage<'a, M> {
+    pub fn parse(memory: &'a M, header: &[u8], load_bias: u64) -> Option<Self> {
+        let class64 = match *header.get(4)? {
+            ELFCLASS64 => true,
+            ELFCLASS32 => false,
+            _ => return None,
+        };
+
+        Some(Self { memory, class64, load_bias, program_headers: program_headers(header)? })
+    }
+
+    pub fn load_segments(&self) -> impl Iterator<Item = &ProgramHeader> {
+        self.program_headers.iter().filter(|header| header.p_type == PT_LOAD)
+    }
+
+    fn contains_vaddr(&self, vaddr: u64) -> bool {
+        self.load_segments().any(|segment| (page_start(segment.p_vaddr)..segment.p_vaddr + segment.p_memsz).contains(&vaddr))
+    }
+
+    // glibc relocates the pointers of the dynamic section in place while bionic leaves them untouched
+    fn to_vaddr(&self, pointer: u64) -> u64 {
+        match pointer.checked_sub(self.load_bias) {
+            Some(vaddr) if self.load_bias != 0 && self.contains_vaddr(vaddr) => vaddr,
+            _ => pointer,
+        }
+    }
+
+    pub fn dynamic_entries(&self) -> Vec<DynamicEntry> {
+        let Some(dynamic) = self.program_headers.iter().find(|header| header.p_type == PT_DYNAMIC) else {
+            return Vec::new();
+        };
+        let Some(data) = self.memory.read(dynamic.p_vaddr, dynamic.p_filesz as usize) else {
+            return Vec::new();
+        };
+
+        let entry_size = if self.class64 { 16 } else { 8 };
+        data.chunks_exact(entry_size).map_while(|entry| {
+            let tag = read_word(entry, 0, self.class64)?;
+            (tag != DT_NULL).then(|| DynamicEntry { tag, value: read_word(entry, entry_size / 2, self.class64).unwrap() })
+        }).collect()
+    }
+
+    fn dynamic_pointer(entries: &[DynamicEntry], tag: u64) -> Option<u64> {
+        entries.iter().find(|entry| entry.tag == tag).map(|entry| entry.value)
+    }
+
+    // the symbol count isn't stored in the dynamic section, it is read from the hash tables
+    fn symbol_count(&self, entries: &[DynamicEntry]) -> Option<usize> {
+        if let Some(hash) = Self::dynamic_pointer(entries, DT_HASH) {
+            return read_u32(self.memory.read(self.to_vaddr(hash) + 4, 4)?, 0).map(|count| count as usize);
+        }
+
+        let gnu_hash = self.to_vaddr(Self::dynamic_pointer(entries, DT_GNU_HASH)?);
+        let header = self.memory.read(gnu_hash, 16)?;
+        let (bucket_count, symbol_offset, bloom_size) = (read_u32(header, 0)? as u64, read_u32(header, 4)?, read_u32(header, 8)? as u64);
+        let buckets_start = gnu_hash + 16 + bloom_size * if self.class64 { 8 } else { 4 };
+        let buckets = self.memory.read(buckets_start, bucket_count as usize * 4)?;
+
+        let Some(mut last_symbol) = buckets.chunks_exact(4).map(|bucket| read_u32(bucket, 0).unwrap()).max().filter(|&index| index >= symbol_offset) else {
+            return Some(symbol_offset as usize);
+        };
+
+        // the chain of the last bucket ends with the last symbol, its value has the lowest bit set
+        let chains_start = buckets_start + bucket_count * 4;
+        loop {
+            let chain = read_u32(self.memory.read(chains_start + (last_symbol - symbol_offset) as u64 * 4, 4)?, 0)?;
+            if chain & 1 != 0 {
+                return Some(last_symbol as usize + 1);
+            }
+            last_symbol += 1;
+        }
+    }
+
+    // defined global and weak symbols of the dynamic symbol table
+    pub fn exports(&self) -> Vec<Symbol> {
+        let entries = self.dynamic_entries();
+        let (Some(symtab), Some(strtab), Some(strsz), Some(count)) = (
+            Self::dynamic_pointer(&entries, DT_SYMTAB),
+            Self::dynamic_pointer(&entries, DT_STRTAB),
+            Self::dynamic_pointer(&entries, DT_STRSZ),
+            self.symbol_count(&entries),
+        ) else {
+            return Vec::new();
+        };
+
+        let symbol_size = if self.class64 { 24 } else { 16 };
+        let (Some(symbols), Some(strings)) = (
+            self.memory.read(self.to_vaddr(symtab), count * symbol_size),
+            self.memory.read(self.to_vaddr(strtab), strsz as usize),
+        ) else {
+            return Vec::new();
+        };
+
+        symbols.chunks_exact(symbol_size).filter_map(|symbol| {
+            let name = read_u32(symbol, 0)? as usize;
+            let (info, section, value, size) = if self.class64 {
+                (symbol[4], read_u16(symbol, 6)?, read_u64(symbol, 8)?, read_u64(symbol, 16)?)
+            } else {
+                (symbol[12], read_u16(symbol, 14)?, read_u32(symbol, 4)? as u64, read_u32(symbol, 8)? as u64)
+            };
+
+            if section == SHN_UNDEF || !matches!(info >> 4, STB_GLOBAL | STB_WEAK) {
+                return None;
+            }
+
+            let name = strings.get(name..)?;
+            let name = &name[..name.iter().position(|&byte| byte == 0)?];
+            (!name.is_empty()).then(|| Symbol { name: String::from_utf8_lossy(name).to_string(), value, size })
+        }).collect()
+    }
+
+    pub fn find_export(&self, name: &str) -> Option<Symbol> {
+        self.exports().into_iter().find(|symbol| symbol.name == name)
+    }
+
+    pub fn build_id(&self) -> Option<Vec<u8>> {
+        self.program_headers.iter().filter(|header| header.p_type == PT_NOTE).find_map(|header| {
+            find_build_id(self.memory.read(header.p_vaddr, header.p_filesz as usize)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYMTAB: usize = 0x200;
+    const STRTAB: usize = 0x300;
+    const GNU_HASH: usize = 0x380;
+    const HASH: usize = 0x3C0;
+    const DYNAMIC: usize = 0x400;
+    const NOTE: usize = 0x480;
+
+    fn write(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // ELF64 library laid out at its virtual addresses, the dynamic pointers are shifted by the given bias
+    fn library_image(hash_tag: u64, pointer_bias: u64) -> Vec<u8> {
+        let mut image = vec![0u8; 0x1000];
+        write(&mut image, 0, ELF_MAGIC);
+        image[4] = ELFCLASS64;
+        image[5] = 1;
+        write(&mut image, 0x20, &64u64.to_le_bytes());
+        write(&mut image, 0x36, &56u16.to_le_bytes());
+        write(&mut image, 0x38, &3u16.to_le_bytes());
+
+        for (index, (p_type, offset, size)) in [(PT_LOAD, 0, 0x1000), (PT_DYNAMIC, DYNAMIC, 0x60), (PT_NOTE, NOTE, 0x14)].into_iter().enumerate() {
+            let header = 64 + index * 56;
+            write(&mut image, header, &p_type.to_le_bytes());
+            write(&mut image, header + 0x8, &(offset as u64).to_le_bytes());
+            write(&mut image, header + 0x10, &(offset as u64).to_le_bytes());
+            write(&mut image, header + 0x20, &(size as u64).to_le_bytes());
+            write(&mut image, header + 0x28, &(size as u64).to_le_bytes());
+        }
+
+        let strings = b"\0sqlite3_open\0local\0imported\0weak\0";
+        write(&mut image, STRTAB, strings);
+
+        // name, binding << 4 | type, section, value
+        for (index, (name, info, section, value)) in [(0u32, 0u8, 0u16, 0u64), (1, 0x12, 9, 0x5000), (14, 0x02, 9, 0x5100), (20, 0x12, 0, 0), (29, 0x22, 9, 0x5200)].into_iter().enumerate() {
+            let symbol = SYMTAB + index * 24;
+            write(&mut image, symbol, &name.to_le_bytes());
+            image[symbol + 4] = info;
+            write(&mut image, symbol + 6, &section.to_le_bytes());
+            write(&mut image, symbol + 8, &value.to_le_bytes());
+            write(&mut image, symbol + 16, &0x40u64.to_le_bytes());
+        }
+
+        // one bucket starting at the first symbol, the chain ends on the fifth
+        for (offset, word) in [(0, 1u32), (4, 1), (8, 1), (24, 1), (28, 0), (32, 0), (36, 0), (40, 1)] {
+            write(&mut image, GNU_HASH + offset, &word.to_le_bytes());
+        }
+        write(&mut image, HASH, &1u32.to_le_bytes());
+        write(&mut image, HASH + 4, &5u32.to_le_bytes());
+
+        for (index, (tag, value)) in [(DT_SYMTAB, SYMTAB as u64), (DT_STRTAB, STRTAB as u64), (DT_STRSZ, strings.len() as u64), (hash_tag, if hash_tag == DT_HASH { HASH } else { GNU_HASH } as u64)].into_iter().enumerate() {
+            let value = if tag == DT_STRSZ { value } else { value + pointer_bias };
+            write(&mut image, DYNAMIC + index * 16, &tag.to_le_bytes());
+            write(&mut image, DYNAMIC + index * 16 + 8, &value.to_le_bytes());
+        }
+
+        write(&mut image, NOTE, &4u32.to_le_bytes());
+        write(&mut image, NOTE + 4, &4u32.to_le_bytes());
+        write(&mut image, NOTE + 8, &NT_GNU_BUILD_ID.to_le_bytes());
+        write(&mut image, NOTE + 12, b"GNU\0");
+        write(&mut image, NOTE + 16, &[0xDE, 0xAD, 0xBE, 0xEF]);
+        image
+    }
+
+    fn export_names(image: &LoadedImage<[u8]>) -> Vec<String> {
+        image.exports().into_iter().map(|symbol| symbol.name).collect()
+    }
+
+    #[test]
+    fn reads_exports_from_hash_tables() {
+        for hash_tag in [DT_GNU_HASH, DT_HASH] {
+            let library = library_image(hash_tag, 0);
+            let image = LoadedImage::parse(library.as_slice(), &library, 0).unwrap();
+
+            assert_eq!(image.dynamic_entries().len(), 4);
+            assert_eq!(export_names(&image), ["sqlite3_open", "weak"]);
+            assert_eq!(image.find_export("sqlite3_open"), Some(Symbol { name: "sqlite3_open".into(), value: 0x5000, size: 0x40 }));
+            assert_eq!(image.find_export("local"), None);
+        }
+    }
+
+    #[test]
+    fn reads_relocated_dynamic_pointers() {
+        let library = library_image(DT_GNU_HASH, 0x7000_0000);
+        let image = LoadedImage::parse(library.as_slice(), &library, 0x7000_0000).unwrap();
+
+        assert_eq!(export_names(&image), ["sqlite3_open", "weak"]);
+    }
+
+    #[test]
+    fn reads_build_id() {
+        let library = library_image(DT_HASH, 0);
+        let image = LoadedImage::parse(library.as_slice(), &library, 0).unwrap();
+
+        assert_eq!(image.build_id(), Some(vec![0xDE, 0xAD, 0xBE, 0xEF]));
+        assert_eq!(image.load_segments().count(), 1);
+    }
+
+    #[test]
+    fn rejects_oversized_notes() {
+        let mut notes = Vec::new();
+        for word in [u32::MAX, 4, NT_GNU_BUILD_ID] {
+            notes.extend(word.to_le_bytes());
+        }
+        notes.extend(b"GNU\0\xDE\xAD\xBE\xEF");
+        assert_eq!(find_build_id(&notes), None);
+
+        notes[..4].copy_from_slice(&4u32.to_le_bytes());
+        notes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(find_build_id(&notes), None);
+    }
+}
@@ -1,36 +1,291 @@
-use std::sync::Mutex;
+use std::{cell::Cell, error::Error, fmt, sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Mutex}, thread::LocalKey, time::{SystemTime, UNIX_EPOCH}};
+
+use serde::Serialize;
+
+use crate::sig::ResolvedSignature;
 
 pub static MUTEX: Mutex<()> = Mutex::new(());
 
+// how the address of a hooked function was found
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    Symbol,
+    Signature,
+    // signature restored from the cache supplied at init
+    Cache,
+    // function pointer read from a table, like the JNI interface
+    Pointer,
+}
+
+impl From<ResolvedSignature> for Resolution {
+    fn from(signature: ResolvedSignature) -> Self {
+        if signature.cached { Resolution::Cache } else { Resolution::Signature }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum HookError {
+    SymbolNotFound { library: String, symbol: String },
+    SignatureNotFound { signature: String },
+    Dobby { address: usize, message: String },
+    AlreadyHooked { hook: &'static str },
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookError::SymbolNotFound { library, symbol } => write!(f, "symbol {} not found in {}", symbol, library),
+            HookError::SignatureNotFound { signature } => write!(f, "signature {} not found", signature),
+            HookError::Dobby { address, message } => write!(f, "dobby failed at {:#x}: {}", address, message),
+            HookError::AlreadyHooked { hook } => write!(f, "{} is already hooked", hook),
+        }
+    }
+}
+
+impl Error for HookError {}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HookRecord {
+    pub name: &'static str,
+    pub module: String,
+    // native module the hook was installed by, it is removed when the module shuts down
+    pub owner: Option<&'static str>,
+    // 0 when the address could not be resolved
+    pub address: usize,
+    pub resolution: Resolution,
+    // false without an error once the hook is removed
+    pub installed: bool,
+    pub error: Option<HookError>,
+    // milliseconds since the unix epoch
+    pub installed_at: u64,
+    // nested calls sent straight to the original by the re-entrancy guard
+    pub reentries_avoided: u64,
+    #[serde(skip)]
+    control: &'static HookControl,
+}
+
+/*
+ * state of a hook defined with def_hook
+ * - a disabled hook passes every call straight to the original
+ * - the trampoline is never freed, threads may still be running the hook or the original when it is removed
+ */
+#[derive(Debug)]
+pub struct HookControl {
+    enabled: AtomicBool,
+    // address the trampoline was installed at, 0 until the first installation
+    address: AtomicUsize,
+    // nested calls of a guarded hook sent straight to the original
+    reentries_avoided: AtomicU64,
+}
+
+impl HookControl {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self { enabled: AtomicBool::new(false), address: AtomicUsize::new(0), reentries_avoided: AtomicU64::new(0) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    pub fn address(&self) -> Option<usize> {
+        Some(self.address.load(Ordering::Acquire)).filter(|&address| address != 0)
+    }
+
+    // called once the original is set, the hook body runs from then on
+    pub fn enable(&self, address: usize) {
+        self.address.store(address, Ordering::Release);
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::Release);
+    }
+
+    pub fn reentries_avoided(&self) -> u64 {
+        self.reentries_avoided.load(Ordering::Relaxed)
+    }
+}
+
+static HOOKS: Mutex<Vec<HookRecord>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static INSTALLING_MODULE: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+// hooks installed by the closure belong to the module
+pub fn install_for<T>(module: &'static str, install: impl FnOnce() -> T) -> T {
+    struct Restore(Option<&'static str>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            INSTALLING_MODULE.set(self.0);
+        }
+    }
+
+    let _restore = Restore(INSTALLING_MODULE.replace(Some(module)));
+    install()
+}
+
+// marks a guarded hook as running on the current thread until dropped, each hook has its own flag
+pub struct ReentrancyGuard {
+    running: &'static LocalKey<Cell<bool>>,
+}
+
+impl ReentrancyGuard {
+    // None when the hook is already running on this thread, the call is then counted on its control
+    pub fn enter(running: &'static LocalKey<Cell<bool>>, control: &HookControl) -> Option<Self> {
+        match running.try_with(|running| running.replace(true)) {
+            Ok(false) => Some(Self { running }),
+            Ok(true) => {
+                control.reentries_avoided.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            // the thread is exiting, its hooks can't be tracked anymore
+            Err(_) => None,
+        }
+    }
+}
+
+impl Drop for ReentrancyGuard {
+    fn drop(&mut self) {
+        let _ = self.running.try_with(|running| running.set(false));
+    }
+}
+
+// records the result of a hook installation and passes it through
+pub fn record(name: &'static str, control: &'static HookControl, module: &str, resolution: Resolution, address: usize, result: Result<(), HookError>) -> Result<(), HookError> {
+    match &result {
+        Ok(()) => debug!("Hooked {} at {:#x} in {} ({:?})", name, address, module, resolution),
+        Err(error) => warn!("Failed to hook {} in {}: {}", name, module, error),
+    }
+
+    // the installed hook keeps its record
+    if let Err(HookError::AlreadyHooked { .. }) = result {
+        return result;
+    }
+
+    let record = HookRecord {
+        name,
+        module: module.to_string(),
+        owner: INSTALLING_MODULE.get(),
+        address,
+        resolution,
+        installed: result.is_ok(),
+        error: result.clone().err(),
+        installed_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as u64),
+        reentries_avoided: 0,
+        control,
+    };
+
+    let mut hooks = HOOKS.lock().unwrap();
+    // a hook installed again replaces its previous record
+    hooks.retain(|hook| hook.name != name);
+    hooks.push(record);
+    result
+}
+
+// disables the hooks installed by the module, calls go straight to the originals while the trampolines stay in place
+pub fn unhook_module(module: &str) {
+    let _guard = MUTEX.lock().unwrap_or_else(|error| error.into_inner());
+
+    for hook in HOOKS.lock().unwrap().iter_mut().filter(|hook| hook.owner == Some(module) && hook.installed) {
+        hook.control.disable();
+        debug!("Unhooked {} at {:#x}", hook.name, hook.address);
+        hook.installed = false;
+        hook.error = None;
+    }
+}
+
+pub fn hooks() -> Vec<HookRecord> {
+    let mut hooks = HOOKS.lock().unwrap().clone();
+    hooks.iter_mut().for_each(|hook| hook.reentries_avoided = hook.control.reentries_avoided());
+    hooks
+}
+
 #[macro_export]
 macro_rules! def_hook {
-    ($func:ident, $ret:ty, | $($arg:ident : $arg_type:ty),* | $body:block) => {
+    // the prelude runs before the body of the hook
+    (@define $func:ident, $ret:ty, ($($arg:ident : $arg_type:ty),*), $body:block, { $($prelude:tt)* }) => {
         paste::item! {
             #[allow(non_upper_case_globals)]
             static mut [<$func _original>]: std::option::Option<extern "C" fn($($arg_type),*) -> $ret> = None;
+            #[allow(non_upper_case_globals)]
+            static [<$func _control>]: $crate::hook::HookControl = $crate::hook::HookControl::new();
 
+            // hooks mirror the native signature they replace, their body runs in an unsafe context
+            #[allow(clippy::too_many_arguments)]
             fn $func($($arg: $arg_type),*) -> $ret {
-                {
-                    #[allow(unused_unsafe)]
-                    unsafe {
-                        $body
-                    }
-                }
+                #[allow(clippy::too_many_arguments)]
+                unsafe fn body($($arg: $arg_type),*) -> $ret $body
+
+                $($prelude)*
+
+                unsafe { body($($arg),*) }
             }
         }
     };
+    (@skip_disabled $func:ident, ($($arg:ident),*)) => {
+        paste::item! {
+            if ![<$func _control>].is_enabled() {
+                return unsafe { [<$func _original>].unwrap()($($arg),*) };
+            }
+        }
+    };
+    ($func:ident, $ret:ty, | $($arg:ident : $arg_type:ty),* | $body:block) => {
+        $crate::def_hook!(@define $func, $ret, ($($arg: $arg_type),*), $body, {
+            $crate::def_hook!(@skip_disabled $func, ($($arg),*));
+        });
+    };
+    // the body also runs while the hook is disabled, for cleanups that must not be skipped
+    (always $func:ident, $ret:ty, | $($arg:ident : $arg_type:ty),* | $body:block) => {
+        $crate::def_hook!(@define $func, $ret, ($($arg: $arg_type),*), $body, {});
+    };
+    // nested calls of the hook on the same thread go straight to the original
+    (guarded $func:ident, $ret:ty, | $($arg:ident : $arg_type:ty),* | $body:block) => {
+        paste::item! {
+            $crate::def_hook!(@define $func, $ret, ($($arg: $arg_type),*), $body, {
+                $crate::def_hook!(@skip_disabled $func, ($($arg),*));
+
+                thread_local! {
+                    static RUNNING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+                }
+
+                let Some(_guard) = $crate::hook::ReentrancyGuard::enter(&RUNNING, &[<$func _control>]) else {
+                    return unsafe { [<$func _original>].unwrap()($($arg),*) };
+                };
+            });
+        }
+    };
 }
 
+// installs the hook and records it in the registry, evaluates to a Result<(), HookError>
 #[macro_export]
 macro_rules! dobby_hook {
-    ($sym:expr, $hook:expr) => {
+    ($module:expr, $resolution:expr, $address:expr, $hook:ident) => {
         paste::item! {
-            unsafe {
-                if let Ok(_) = crate::hook::MUTEX.lock() {
-                    if let Some(ptr) = dobby_rs::hook($sym, $hook as *mut std::ffi::c_void).ok().map(|x| x as *mut std::ffi::c_void) {
-                        [<$hook _original>] = std::mem::transmute(ptr);
-                    }
-                }
+            {
+                let address = $address as *mut std::ffi::c_void;
+                // recorded under the lock, a concurrent installation sees the hook as installed
+                let _guard = $crate::hook::MUTEX.lock().unwrap_or_else(|error| error.into_inner());
+                let result = if [<$hook _control>].is_enabled() {
+                    Err($crate::hook::HookError::AlreadyHooked { hook: stringify!($hook) })
+                } else if let Some(hooked_address) = [<$hook _control>].address() {
+                    // the trampoline of a removed hook is still in place
+                    [<$hook _control>].enable(hooked_address);
+                    Ok(())
+                } else {
+                    unsafe { dobby_rs::hook(address, $hook as *mut std::ffi::c_void) }.map(|original| {
+                        // the original pointer takes the type of the hook
+                        #[allow(clippy::missing_transmute_annotations)]
+                        unsafe { [<$hook _original>] = std::mem::transmute(original); }
+                        [<$hook _control>].enable(address as usize);
+                    }).map_err(|error| $crate::hook::HookError::Dobby { address: address as usize, message: error.to_string() })
+                };
+
+                $crate::hook::record(stringify!($hook), &[<$hook _control>], $module, $resolution, [<$hook _control>].address().unwrap_or(address as usize), result)
             }
         }
     };
@@ -38,13 +293,164 @@ macro_rules! dobby_hook {
 
 #[macro_export]
 macro_rules! dobby_hook_sym {
-    ($lib:expr, $sym:expr, $hook:expr) => {
-        if let Some(hook_symbol) = dobby_rs::resolve_symbol($lib, $sym) {
-            crate::dobby_hook!(hook_symbol, $hook);
-            debug!("hooked symbol: {}", $sym);
-        } else {
-            panic!("Failed to resolve symbol: {}", $sym);
+    ($lib:expr, $sym:expr, $hook:ident) => {
+        match dobby_rs::resolve_symbol($lib, $sym) {
+            Some(hook_symbol) => $crate::dobby_hook!($lib, $crate::hook::Resolution::Symbol, hook_symbol, $hook),
+            None => paste::item! { $crate::hook::record(stringify!($hook), &[<$hook _control>], $lib, $crate::hook::Resolution::Symbol, 0, Err($crate::hook::HookError::SymbolNotFound { library: $lib.to_string(), symbol: $sym.to_string() })) },
+        }
+    };
+}
+
+// resolves the signature in the module, evaluates to a Result<(), HookError>
+#[macro_export]
+macro_rules! dobby_hook_sig {
+    ($mapped_lib:expr, $signature:expr, $hook:ident) => {
+        match $crate::sig::resolve($mapped_lib, $signature) {
+            Some(resolved) => $crate::dobby_hook!($mapped_lib.name(), $crate::hook::Resolution::from(resolved), resolved.address, $hook),
+            None => paste::item! { $crate::hook::record(stringify!($hook), &[<$hook _control>], $mapped_lib.name(), $crate::hook::Resolution::Signature, 0, Err($crate::hook::HookError::SignatureNotFound { signature: $signature.name.to_string() })) },
         }
     };
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_hooked(name: &str) -> bool {
+        HOOKS.lock().unwrap().iter().any(|hook| hook.name == name && hook.installed)
+    }
+
+    static CONTROL: HookControl = HookControl::new();
+
+    fn symbol_not_found(symbol: &str) -> HookError {
+        HookError::SymbolNotFound { library: "libc.so".into(), symbol: symbol.into() }
+    }
+
+    #[test]
+    fn records_hook_results() {
+        assert!(record("registry_hook", &CONTROL, "libc.so", Resolution::Symbol, 0x1000, Ok(())).is_ok());
+        assert!(is_hooked("registry_hook"));
+
+        // a second installation of an installed hook keeps its record
+        let already_hooked = HookError::AlreadyHooked { hook: "registry_hook" };
+        assert_eq!(record("registry_hook", &CONTROL, "libc.so", Resolution::Symbol, 0x2000, Err(already_hooked.clone())), Err(already_hooked));
+        assert!(is_hooked("registry_hook"));
+
+        assert!(record("registry_hook", &CONTROL, "libc.so", Resolution::Symbol, 0, Err(symbol_not_found("open"))).is_err());
+
+        let hooks = hooks().into_iter().filter(|hook| hook.name == "registry_hook").collect::<Vec<_>>();
+        assert_eq!(hooks.len(), 1);
+        assert_eq!(hooks[0].error, Some(symbol_not_found("open")));
+        assert!(!hooks[0].installed && hooks[0].installed_at > 0);
+
+        let json = serde_json::to_value(&hooks[0]).unwrap();
+        assert_eq!(json["resolution"], "symbol");
+        assert_eq!(json["error"]["kind"], "symbol_not_found");
+        assert_eq!(json["error"]["symbol"], "open");
+        assert_eq!(Resolution::from(ResolvedSignature { address: 0x10, cached: true }), Resolution::Cache);
+    }
+
+    #[test]
+    fn removes_the_hooks_of_a_module() {
+        static OWNED: HookControl = HookControl::new();
+        static OTHER: HookControl = HookControl::new();
+        OWNED.enable(0x1000);
+        OTHER.enable(0x2000);
+
+        install_for("registry_module", || {
+            let _ = record("registry_owned_hook", &OWNED, "libc.so", Resolution::Symbol, 0x1000, Ok(()));
+            let _ = record("registry_owned_failed_hook", &CONTROL, "libc.so", Resolution::Symbol, 0, Err(symbol_not_found("fstat")));
+        });
+        let _ = record("registry_other_hook", &OTHER, "libc.so", Resolution::Symbol, 0x2000, Ok(()));
+
+        unhook_module("registry_module");
+        assert!(!OWNED.is_enabled() && OTHER.is_enabled());
+        assert!(!is_hooked("registry_owned_hook") && is_hooked("registry_other_hook"));
+
+        let owners = hooks().into_iter().filter(|hook| hook.name.starts_with("registry_owned")).map(|hook| hook.owner).collect::<Vec<_>>();
+        assert_eq!(owners, [Some("registry_module"), Some("registry_module")]);
+        assert_eq!(symbol_not_found("fstat").to_string(), "symbol fstat not found in libc.so");
+    }
+
+    #[test]
+    fn guards_nested_calls_per_thread() {
+        thread_local! {
+            static RUNNING: Cell<bool> = const { Cell::new(false) };
+            static OTHER_RUNNING: Cell<bool> = const { Cell::new(false) };
+        }
+        static GUARDED: HookControl = HookControl::new();
+
+        let outer = ReentrancyGuard::enter(&RUNNING, &GUARDED).unwrap();
+        assert!(ReentrancyGuard::enter(&RUNNING, &GUARDED).is_none());
+        assert!(ReentrancyGuard::enter(&OTHER_RUNNING, &GUARDED).is_some());
+        assert!(std::thread::spawn(|| ReentrancyGuard::enter(&RUNNING, &GUARDED).is_some()).join().unwrap());
+
+        drop(outer);
+        assert!(ReentrancyGuard::enter(&RUNNING, &GUARDED).is_some());
+        assert_eq!(GUARDED.reentries_avoided(), 1);
+    }
+
+    extern "C" fn toggled_original(value: u32) -> u32 {
+        value + 1
+    }
+
+    def_hook!(
+        toggled_hook,
+        u32,
+        |value: u32| {
+            value * 10
+        }
+    );
+
+    def_hook!(
+        always always_hook,
+        u32,
+        |value: u32| {
+            always_hook_original.unwrap()(value) * 10
+        }
+    );
+
+    #[test]
+    fn disabled_hooks_call_the_original() {
+        unsafe { toggled_hook_original = Some(toggled_original) };
+        assert_eq!(toggled_hook(2), 3);
+
+        toggled_hook_control.enable(0x1000);
+        assert_eq!(toggled_hook(2), 20);
+
+        toggled_hook_control.disable();
+        assert_eq!(toggled_hook(2), 3);
+        assert_eq!(toggled_hook_control.address(), Some(0x1000));
+    }
+
+    #[test]
+    fn always_hooks_run_while_disabled() {
+        unsafe { always_hook_original = Some(toggled_original) };
+        assert_eq!(always_hook(2), 30);
+
+        always_hook_control.enable(0x1000);
+        always_hook_control.disable();
+        assert_eq!(always_hook(2), 30);
+    }
+
+    extern "C" fn guarded_original(depth: u32) -> u32 {
+        100 + depth
+    }
+
+    def_hook!(
+        guarded guarded_hook,
+        u32,
+        |depth: u32| {
+            guarded_hook(depth + 1)
+        }
+    );
+
+    #[test]
+    fn calls_the_original_when_reentered() {
+        unsafe { guarded_hook_original = Some(guarded_original) };
+        guarded_hook_control.enable(0x1000);
+
+        assert_eq!(guarded_hook(0), 101);
+        assert_eq!(guarded_hook_control.reentries_avoided(), 1);
+    }
+}
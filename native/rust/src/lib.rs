@@ -2,88 +2,142 @@
 extern crate log;
 
 mod common;
+mod deferred;
 
 mod hook;
 mod util;
-mod mapped_lib;
+pub mod elf;
+pub mod mapped_lib;
+pub mod memory;
+pub mod offline;
+pub mod zip;
 mod config;
-mod sig;
+pub mod sig;
 
 mod modules;
+pub mod target;
 
 use android_logger::Config;
 use log::LevelFilter;
-use modules::{composer_hook, custom_font_hook, duplex_hook, fstat_hook, linker_hook, sqlite_hook, unary_call_hook};
+use modules::{composer_hook, linker_hook, sqlite_hook};
 
-use jni::objects::{JObject, JString};
+use jni::objects::{JObject, JString, JValue};
 use jni::sys::{jint, jstring, JNI_VERSION_1_6};
 use jni::{JNIEnv, JavaVM, NativeMethod};
 use util::get_jni_string;
 
 use std::ffi::c_void;
-use std::thread::JoinHandle;
+
+// every signature the modules resolve in libclient.so
+pub fn builtin_signatures() -> Vec<&'static sig::SignatureDef> {
+    modules::MODULES.iter().flat_map(|module| module.signatures().iter().copied()).collect()
+}
 
 fn pre_init() {
     debug!("Pre init");
-    linker_hook::init();
-    custom_font_hook::init();
-    fstat_hook::init();
+    modules::pre_init();
 }
 
-fn init(mut env: JNIEnv, _class: JObject, signature_cache: JString) -> jstring {
+fn init(mut env: JNIEnv, _class: JObject, signature_cache: JString, signature_definitions: JString, client_version: JString) -> jstring {
     debug!("Initializing native lib");
 
     let start_time = std::time::Instant::now();
 
-    // load signature cache
-    
-    if !signature_cache.is_null() {
-        let sig_cache_str = get_jni_string(&mut env, signature_cache).expect("Failed to convert mappings to string");
-        
-        if let Ok(signature_cache) = serde_json::from_str(sig_cache_str.as_str()) {
-            sig::add_signatures(signature_cache);
-        } else {
-            error!("Failed to load signature cache");
+    // the client version selects which signature candidates apply
+    if !client_version.is_null() {
+        sig::definition::set_client_version(&get_jni_string(&mut env, client_version).expect("Failed to convert client version to string"));
+    }
+
+    // definitions supplied by the manager replace the built-in candidates, the whole document is ignored if it is invalid
+    if !signature_definitions.is_null() {
+        let document = get_jni_string(&mut env, signature_definitions).expect("Failed to convert signature definitions to string");
+        let known_signatures = builtin_signatures().iter().map(|signature| signature.name).collect::<Vec<_>>();
+
+        if let Ok(count) = sig::overrides::load_definitions(&document, &known_signatures) {
+            info!("Loaded definitions for {} signatures", count);
         }
     }
 
-    common::set_native_lib_instance(env.new_global_ref(_class).ok().expect("Failed to create global ref"));
+    let sig_cache_str = (!signature_cache.is_null()).then(|| {
+        get_jni_string(&mut env, signature_cache).expect("Failed to convert mappings to string")
+    });
 
-    let _ = common::CLIENT_MODULE;
+    common::set_native_lib_instance(env.new_global_ref(_class).expect("Failed to create global ref"));
 
-    // initialize modules asynchronously
+    // resolve the signatures of the enabled modules in a single pass so the hooks only hit the cache
+    let signatures = modules::enabled_signatures();
 
-    let mut threads: Vec<JoinHandle<()>> = Vec::new();
+    // load signature cache, entries computed for another build of the module are dropped. deferred before the hooks so they run after it
+    let cached_signatures = sig_cache_str.clone();
 
-    macro_rules! async_init {
-        ($($f:expr),*) => {
-            $(
-                threads.push(std::thread::spawn(move || {
-                    $f;
-                }));
-            )*
-        };
-    }
+    deferred::defer("signature_cache", &target::CLIENT, move |client_module| {
+        sig::set_cache_module(client_module);
 
-    async_init!(
-        duplex_hook::init(),
-        unary_call_hook::init(),
-        composer_hook::init(),
-        sqlite_hook::init()
-    );
-    
-    threads.into_iter().for_each(|t| t.join().unwrap());
+        if let Some(sig_cache_str) = cached_signatures {
+            match serde_json::from_str(sig_cache_str.as_str()) {
+                Ok(signature_cache) => sig::add_signatures(signature_cache),
+                Err(error) => error!("Failed to load signature cache: {}", error),
+            }
+        }
+
+        sig::set_scan_budget(config::native_config().signature_scan_budget);
+        sig::prefetch_signatures(client_module, &signatures);
+        Ok(())
+    });
+
+    modules::init();
+    modules::defer();
+
+    // deferred after the hooks so the cache holds the signatures they resolved
+    deferred::defer("signature_cache_update", &target::CLIENT, |_| send_signature_cache());
 
     info!("native init took {:?}", start_time.elapsed());
 
-    // send back the signature cache
-    if let Ok(signature_cache) = serde_json::to_string(&sig::get_signatures()) {
-        env.new_string(signature_cache).ok().expect("Failed to create new string").into_raw()
+    // the hooks are installed in the background, the refreshed cache is sent once libclient.so is loaded
+    env.new_string(sig_cache_str.unwrap_or_default()).expect("Failed to create new string").into_raw()
+}
+
+fn send_signature_cache() -> Result<(), String> {
+    let signature_cache = serde_json::to_string(&sig::get_signatures()).map_err(|error| error.to_string())?;
+    let mut result = Ok(());
+
+    common::attach_jni_env(|env| {
+        result = env.new_string(signature_cache).and_then(|signature_cache| {
+            env.call_method(common::native_lib_instance(), "onNativeSignatureCache", "(Ljava/lang/String;)V", &[JValue::Object(&signature_cache)])
+        }).map(|_| ()).map_err(|error| format!("Failed to send the signature cache: {}", error));
+    });
+    result
+}
+
+fn get_signature_report(env: JNIEnv, _class: JObject) -> jstring {
+    if let Ok(report) = serde_json::to_string(&sig::report::report()) {
+        env.new_string(report).expect("Failed to create new string").into_raw()
     } else {
         std::ptr::null_mut()
     }
 }
 
+fn get_hook_status(env: JNIEnv, _class: JObject) -> jstring {
+    if let Ok(status) = serde_json::to_string(&deferred::hook_status()) {
+        env.new_string(status).expect("Failed to create new string").into_raw()
+    } else {
+        std::ptr::null_mut()
+    }
+}
+
+fn get_hooks(env: JNIEnv, _class: JObject) -> jstring {
+    if let Ok(hooks) = serde_json::to_string(&hook::hooks()) {
+        env.new_string(hooks).expect("Failed to create new string").into_raw()
+    } else {
+        std::ptr::null_mut()
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "system" fn JNI_OnUnload(_vm: JavaVM, _: *mut c_void) {
+    modules::shutdown();
+}
 
 #[allow(non_snake_case)]
 #[no_mangle]
@@ -116,9 +170,24 @@ pub extern "system" fn JNI_OnLoad(_vm: JavaVM, _: *mut c_void) -> jint {
             },
             NativeMethod {
                 name: "init".into(),
-                sig: "(Ljava/lang/String;)Ljava/lang/String;".into(),
+                sig: "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;".into(),
                 fn_ptr: init as *mut c_void,
             },
+            NativeMethod {
+                name: "getHookStatus".into(),
+                sig: "()Ljava/lang/String;".into(),
+                fn_ptr: get_hook_status as *mut c_void,
+            },
+            NativeMethod {
+                name: "getHooks".into(),
+                sig: "()Ljava/lang/String;".into(),
+                fn_ptr: get_hooks as *mut c_void,
+            },
+            NativeMethod {
+                name: "getSignatureReport".into(),
+                sig: "()Ljava/lang/String;".into(),
+                fn_ptr: get_signature_report as *mut c_void,
+            },
             NativeMethod {
                 name: "loadConfig".into(),
                 sig: "(Lme/rhunk/snapenhance/nativelib/NativeConfig;)V".into(),
@@ -1,18 +1,69 @@
-use std::error::Error;
+use std::{collections::HashMap, error::Error, path::Path};
 
-use procfs::process::{MMPermissions, MMapPath};
+use once_cell::sync::OnceCell;
+use procfs::process::{MMPermissions, MMapPath, MemoryMap};
+
+use crate::{elf::{self, LoadedImage, Symbol, VirtualMemory}, memory::{self, MemoryView}, zip::{self, ZipEntry}};
 
 #[derive(Debug)]
-pub(crate) struct MappedRegion {
+pub struct MappedRegion {
     pub start: u64,
     pub end: u64,
     pub perms: MMPermissions,
+    pub offset: u64,
+    pub path: String,
+    // execute-only and protected pages are copied once through the memory fallbacks
+    copy: OnceCell<Option<Vec<u8>>>,
+}
+
+impl MappedRegion {
+    // safety: start..end must stay mapped in the current process while the region is alive, readable when perms has READ
+    pub(crate) unsafe fn new(start: u64, end: u64, perms: MMPermissions, offset: u64, path: String) -> Self {
+        Self { start, end, perms, offset, path, copy: OnceCell::new() }
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        (self.start..self.end).contains(&address)
+    }
+
+    pub fn view(&self) -> Option<MemoryView<'_>> {
+        let length = (self.end - self.start) as usize;
+
+        if self.perms.contains(MMPermissions::READ) {
+            return Some(MemoryView::new(self.start, unsafe { std::slice::from_raw_parts(self.start as *const u8, length) }));
+        }
+
+        self.copy.get_or_init(|| {
+            let mut buffer = vec![0u8; length];
+            memory::read_memory(self.start, &mut buffer).map_err(|error| {
+                warn!("Unable to read {:#x}-{:#x} of {}: {}", self.start, self.end, self.path, error);
+            }).ok().map(|_| buffer)
+        }).as_deref().map(|bytes| MemoryView::new(self.start, bytes))
+    }
+
+    // empty when the region can't be read
+    pub fn bytes(&self) -> &[u8] {
+        self.view().map_or(&[], |view| view.bytes())
+    }
+}
+
+// file a library is mapped from, libraries stored in an apk start at the offset of their entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MappedFile {
+    device: (i32, i32),
+    inode: u64,
+    library_offset: u64,
 }
 
 #[derive(Debug)]
-pub(crate) struct MappedLib {
+pub struct MappedLib {
     name: String,
-    pub regions: Vec<MappedRegion>,
+    pub(crate) regions: Vec<MappedRegion>,
+    // size of the library file, or of its entry when it is stored in an apk
+    pub(crate) file_size: Option<u64>,
+    // parsed once from the ELF image, the regions don't change once found
+    load_bias: OnceCell<Option<usize>>,
+    exports: OnceCell<Vec<Symbol>>,
 }
 
 impl MappedLib {
@@ -20,30 +71,215 @@ impl MappedLib {
         Self {
             name,
             regions: Vec::new(),
+            file_size: None,
+            load_bias: OnceCell::new(),
+            exports: OnceCell::new(),
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn regions(&self) -> &[MappedRegion] {
+        &self.regions
+    }
+
+    pub fn file_size(&self) -> Option<u64> {
+        self.file_size
+    }
+
     pub fn search(&mut self) -> Result<&Self, Box<dyn Error>> {
-        procfs::process::Process::myself()?.maps()?.iter().for_each(|map| {
-            let pathname = &map.pathname;
-
-            if let MMapPath::Path(path_buffer) = pathname {
-                let path = path_buffer.to_string_lossy();
-
-                if path.contains(&self.name) {
-                    self.regions.push(MappedRegion {
-                        start: map.address.0,
-                        end: map.address.1,
-                        perms: map.perms,
-                    });
+        let maps = procfs::process::Process::myself()?.maps()?;
+        unsafe { self.search_maps(&maps.0) }
+    }
+
+    /*
+     * finds the regions of the library in the given memory mappings
+     * - files are matched on their exact file name
+     * - libraries stored uncompressed in an apk are mapped straight from the archive, they are matched against its central directory
+     * - regions are grouped by file and library offset, the first group holding the ELF header is kept
+     * safety: the maps must be those of the current process, the regions are read at their addresses
+     */
+    pub(crate) unsafe fn search_maps(&mut self, maps: &[MemoryMap]) -> Result<&Self, Box<dyn Error>> {
+        let mut archives: HashMap<&Path, Vec<ZipEntry>> = HashMap::new();
+        let mut groups: Vec<(MappedFile, Option<u64>, Vec<MappedRegion>)> = Vec::new();
+
+        for map in maps {
+            let MMapPath::Path(path) = &map.pathname else {
+                continue;
+            };
+
+            let (library_offset, apk_entry_size) = if path.file_name().is_some_and(|file_name| file_name == self.name.as_str()) {
+                (0, None)
+            } else if path.extension().is_some_and(|extension| extension == "apk") {
+                let entries = archives.entry(path).or_insert_with(|| {
+                    zip::zip_file_entries(path).unwrap_or_else(|error| {
+                        debug!("Unable to list the entries of {}: {}", path.display(), error);
+                        Vec::new()
+                    })
+                });
+
+                let Some(entry) = entries.iter().find(|entry| entry.stored && entry.file_name() == self.name && entry.contains_offset(map.offset)) else {
+                    continue;
+                };
+                (entry.data_offset, Some(entry.size))
+            } else {
+                continue;
+            };
+
+            let region = unsafe { MappedRegion::new(map.address.0, map.address.1, map.perms, map.offset, path.to_string_lossy().to_string()) };
+            let file = MappedFile { device: map.dev, inode: map.inode, library_offset };
+
+            match groups.iter_mut().find(|(group_file, _, _)| *group_file == file) {
+                Some((_, _, regions)) => regions.push(region),
+                None => {
+                    // the size of the whole apk would change with any of its other entries
+                    let file_size = apk_entry_size.or_else(|| std::fs::metadata(path).ok().map(|metadata| metadata.len()));
+                    groups.push((file, file_size, vec![region]));
                 }
             }
-        });
+        }
 
-        if self.regions.is_empty() {
+        let mut loaded = groups.into_iter().filter(|(file, _, regions)| regions.iter().any(|region| region.offset == file.library_offset));
+
+        let Some((_, file_size, regions)) = loaded.next() else {
             return Err(format!("No regions found for {}", self.name).into());
+        };
+
+        let ignored = loaded.count();
+        if ignored > 0 {
+            warn!("Found {} other mappings of {}, using {}", ignored, self.name, regions[0].path);
         }
 
+        self.regions = regions;
+        self.file_size = file_size;
+        self.load_bias = OnceCell::new();
+        self.exports = OnceCell::new();
         Ok(self)
     }
+
+    // region holding the ELF header, the library may be preceded by other mappings of the same apk
+    fn header_region(&self) -> Option<&MappedRegion> {
+        self.regions.iter()
+            .filter(|region| region.perms.contains(MMPermissions::READ) && region.end > region.start)
+            .filter(|region| elf::is_elf(region.bytes()))
+            .min_by_key(|region| region.start)
+    }
+
+    // difference between the load address and the virtual addresses of the library
+    pub fn load_bias(&self) -> Option<usize> {
+        *self.load_bias.get_or_init(|| {
+            let header_region = self.header_region()?;
+            let first_segment = elf::program_headers(header_region.bytes())?.into_iter()
+                .filter(|header| header.p_type == elf::PT_LOAD)
+                .min_by_key(|header| header.p_offset)?;
+
+            (header_region.start as usize).checked_sub(memory::page_start(first_segment.p_vaddr) as usize)
+        })
+    }
+
+    pub fn elf(&self) -> Option<LoadedImage<'_, Self>> {
+        LoadedImage::parse(self, self.header_region()?.bytes(), self.load_bias()? as u64)
+    }
+
+    // address of an exported symbol, thumb functions have the thumb bit set
+    pub fn find_export(&self, name: &str) -> Option<usize> {
+        let symbol = self.exports().iter().find(|symbol| symbol.name == name)?;
+        Some(self.load_bias()? + symbol.value as usize)
+    }
+
+    pub fn exports(&self) -> &[Symbol] {
+        self.exports.get_or_init(|| self.elf().map(|image| image.exports()).unwrap_or_default())
+    }
+
+    pub fn build_id(&self) -> Option<Vec<u8>> {
+        self.elf()?.build_id()
+    }
+}
+
+impl VirtualMemory for MappedLib {
+    fn read(&self, vaddr: u64, length: usize) -> Option<&[u8]> {
+        let address = self.load_bias()? as u64 + vaddr;
+        let region = self.regions.iter().find(|region| region.perms.contains(MMPermissions::READ) && region.contains(address))?;
+        region.view()?.read(address, length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_char, c_void, CString};
+
+    use procfs::{process::MemoryMaps, FromRead};
+
+    use super::*;
+
+    fn memory_maps(maps: &str) -> Vec<MemoryMap> {
+        MemoryMaps::from_read(maps.as_bytes()).unwrap().0
+    }
+
+    fn region_offsets(lib: &MappedLib) -> Vec<(u64, u64)> {
+        lib.regions.iter().map(|region| (region.start, region.offset)).collect()
+    }
+
+    extern "C" {
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    // the libc of the host is a real library loaded by the system linker
+    #[test]
+    fn resolves_exports_of_a_loaded_library() {
+        let mut libc = MappedLib::new("libc.so.6".into());
+        libc.search().unwrap();
+
+        let name = CString::new("getpid").unwrap();
+        let expected = unsafe { dlsym(std::ptr::null_mut(), name.as_ptr()) } as usize;
+
+        assert!(libc.load_bias().is_some());
+        assert_eq!(libc.find_export("getpid"), Some(expected));
+        assert!(libc.exports().len() > 100);
+        assert!(libc.build_id().is_some_and(|build_id| !build_id.is_empty()));
+    }
+
+    #[test]
+    fn matches_exact_file_names() {
+        let maps = memory_maps("\
+7000000000-7000001000 r--p 00000000 fd:01 200 /data/local/tmp/libclient.so.bak
+7100000000-7100001000 r--p 00003000 fd:01 300 /data/app/~~other/libclient.so
+7200000000-7200001000 r--p 00000000 fd:01 100 /data/app/~~snapchat/lib/arm64/libclient.so
+7200001000-7200003000 r-xp 00001000 fd:01 100 /data/app/~~snapchat/lib/arm64/libclient.so
+7200003000-7200004000 rw-p 00003000 fd:01 100 /data/app/~~snapchat/lib/arm64/libclient.so
+7300000000-7300001000 r--p 00000000 fd:01 400 /system/lib64/libclient.so.so
+");
+        let mut lib = MappedLib::new("libclient.so".into());
+        // the regions of the fake maps are never read
+        unsafe { lib.search_maps(&maps) }.unwrap();
+
+        assert_eq!(region_offsets(&lib), [(0x7200000000, 0), (0x7200001000, 0x1000), (0x7200003000, 0x3000)]);
+        assert!(unsafe { MappedLib::new("libclient".into()).search_maps(&maps) }.is_err());
+    }
+
+    #[test]
+    fn finds_libraries_stored_in_apks() {
+        let library = vec![0u8; 0x3000];
+        let archive = zip::stored_zip(&[("AndroidManifest.xml", b"manifest"), ("lib/arm64-v8a/libclient.so", &library)]);
+        let data_offset = zip::zip_entries(&archive).unwrap()[1].data_offset;
+
+        let path = std::env::temp_dir().join(format!("split_config_{}.apk", std::process::id()));
+        std::fs::write(&path, &archive).unwrap();
+
+        let maps = memory_maps(&format!("\
+7000000000-7000001000 r--p 00000000 fd:01 500 {path}
+7100000000-7100001000 r--p {:08x} fd:01 500 {path}
+7100001000-7100002000 r-xp {:08x} fd:01 500 {path}
+", data_offset, data_offset + 0x1000, path = path.display()));
+
+        let mut lib = MappedLib::new("libclient.so".into());
+        let result = unsafe { lib.search_maps(&maps) }.map(|_| ());
+        std::fs::remove_file(&path).unwrap();
+
+        result.unwrap();
+        assert_eq!(region_offsets(&lib), [(0x7100000000, data_offset), (0x7100001000, data_offset + 0x1000)]);
+        assert_eq!(lib.file_size(), Some(library.len() as u64));
+    }
 }
@@ -0,0 +1,132 @@
+use std::{fs::File, io, os::unix::fs::FileExt};
+
+use nix::libc;
+use once_cell::sync::Lazy;
+
+// 16 KiB on some arm64 devices, the linker aligns the segments to it
+static PAGE_SIZE: Lazy<u64> = Lazy::new(|| match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+    size if size > 0 => size as u64,
+    _ => 0x1000,
+});
+
+// start of the memory page holding the address
+pub fn page_start(address: u64) -> u64 {
+    address & !(*PAGE_SIZE - 1)
+}
+
+// reads the memory of the current process without faulting, unmapped pages fail with an error
+pub fn read_memory(address: u64, buffer: &mut [u8]) -> io::Result<()> {
+    read_memory_vm(address, buffer).or_else(|error| {
+        // process_vm_readv honors the page protections, /proc/self/mem also reads execute-only pages
+        debug!("process_vm_readv failed at {:#x}: {}", address, error);
+        File::open("/proc/self/mem")?.read_exact_at(buffer, address)
+    })
+}
+
+fn read_memory_vm(address: u64, buffer: &mut [u8]) -> io::Result<()> {
+    let local = libc::iovec { iov_base: buffer.as_mut_ptr() as *mut libc::c_void, iov_len: buffer.len() };
+    let remote = libc::iovec { iov_base: address as *mut libc::c_void, iov_len: buffer.len() };
+
+    match unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) } {
+        -1 => Err(io::Error::last_os_error()),
+        read if read as usize == buffer.len() => Ok(()),
+        read => Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("partial read of {} bytes", read))),
+    }
+}
+
+// little endian integers of a byte slice, None past its end
+pub fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    bytes.get(offset..offset.checked_add(2)?).map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes.get(offset..offset.checked_add(4)?).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+pub fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    bytes.get(offset..offset.checked_add(8)?).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+// bytes of a memory range with their address, every read is bounded by the range
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryView<'a> {
+    address: u64,
+    bytes: &'a [u8],
+}
+
+impl<'a> MemoryView<'a> {
+    pub fn new(address: u64, bytes: &'a [u8]) -> Self {
+        Self { address, bytes }
+    }
+
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        address.checked_sub(self.address).is_some_and(|offset| offset < self.bytes.len() as u64)
+    }
+
+    pub fn get(&self, offset: usize, length: usize) -> Option<&'a [u8]> {
+        self.bytes.get(offset..offset.checked_add(length)?)
+    }
+
+    // bytes at an absolute address
+    pub fn read(&self, address: u64, length: usize) -> Option<&'a [u8]> {
+        self.get(address.checked_sub(self.address)?.try_into().ok()?, length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_reads() {
+        let bytes = [1u8, 2, 3, 4];
+        let view = MemoryView::new(0x1000, &bytes);
+
+        assert_eq!(view.get(2, 2), Some(&bytes[2..]));
+        assert_eq!(view.get(2, 3), None);
+        assert_eq!(view.get(usize::MAX, 2), None);
+        assert_eq!(view.read(0x1001, 1), Some(&bytes[1..2]));
+        assert_eq!(view.read(0xFFF, 1), None);
+        assert!(view.contains(0x1003) && !view.contains(0x1004));
+    }
+
+    #[test]
+    fn reads_unreadable_pages() {
+        let page_size = 0x1000;
+        let page = unsafe {
+            libc::mmap(std::ptr::null_mut(), page_size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+        };
+        assert_ne!(page, libc::MAP_FAILED);
+
+        unsafe {
+            (page as *mut u8).add(8).copy_from([0xDE, 0xAD].as_ptr(), 2);
+            assert_eq!(libc::mprotect(page, page_size, libc::PROT_NONE), 0);
+        }
+
+        let mut buffer = [0u8; 2];
+        let result = read_memory(page as u64 + 8, &mut buffer);
+        let vm_result = read_memory_vm(page as u64 + 8, &mut [0u8; 2]);
+        unsafe { libc::munmap(page, page_size) };
+
+        result.unwrap();
+        assert_eq!(buffer, [0xDE, 0xAD]);
+        assert!(vm_result.is_err());
+        assert!(read_memory(0, &mut buffer).is_err());
+    }
+}
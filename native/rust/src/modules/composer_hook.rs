@@ -1,10 +1,12 @@
 #![allow(dead_code, unused_imports)]
 
 use super::util::composer_utils::{ComposerModule, ModuleTag};
-use std::{collections::HashMap, ffi::{c_void, CStr}, sync::Mutex};
+use std::{collections::HashMap, error::Error, ffi::{c_void, CStr}, sync::Mutex};
 use jni::{objects::JString, sys::jobject, JNIEnv};
 use once_cell::sync::Lazy;
-use crate::{common, config, def_hook, dobby_hook, dobby_hook_sym, sig, util::get_jni_string};
+use crate::{common, config::{self, ConfigKey}, deferred, def_hook, dobby_hook_sig, dobby_hook_sym, mapped_lib::MappedLib, sig::{Candidate, SignatureDef}, target, util::get_jni_string};
+
+use super::{NativeModule, Phase};
 
 const JS_TAG_BIG_DECIMAL: i64 = -11;
 const JS_TAG_BIG_INT: i64 = -10;
@@ -63,11 +65,24 @@ struct JsValue {
     tag: i64,
 }
 
+pub const JS_EVAL_SIGNATURE: SignatureDef = SignatureDef {
+    name: "js_eval",
+    candidates: &[
+        Candidate::arm64("00 E4 00 6F 29 00 80 52 76 00 04 8B", -0x28),
+        Candidate::arm("A1 B0 07 92 81 46", -0x7),
+    ],
+};
+
 static AASSET_MAP: Lazy<Mutex<HashMap<usize, Vec<u8>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 static COMPOSER_LOADER_DATA: Mutex<Option<String>> = Mutex::new(None);
 
+/*
+ * the patched assets are served and freed by their handle until they are closed
+ * - a disabled module still serves the assets it opened, they are read consistently
+ * - a buffer handed out by AAsset_getBuffer stays valid until AAsset_close, even after the module shuts down
+ */
 def_hook!(
-    aasset_get_length,
+    always aasset_get_length,
     i32,
     |arg0: *mut c_void| {
         if let Some(buffer) = AASSET_MAP.lock().unwrap().get(&(arg0 as usize)) {
@@ -78,7 +93,7 @@ def_hook!(
 );
 
 def_hook!(
-    aasset_get_buffer,
+    always aasset_get_buffer,
     *const c_void,
     |arg0: *mut c_void| {
         if let Some(buffer) = AASSET_MAP.lock().unwrap().get(&(arg0 as usize)) {
@@ -94,7 +109,7 @@ def_hook!(
     |arg0: *mut c_void, arg1: *const u8, arg2: i32| {
         let handle = aasset_manager_open_original.unwrap()(arg0, arg1, arg2);
 
-        let path = Lazy::new(|| CStr::from_ptr(arg1).to_str().unwrap());
+        let path = Lazy::new(|| CStr::from_ptr(arg1 as *const _).to_str().unwrap());
         if !handle.is_null() && path.starts_with("bridge_observables") {
             let asset_buffer = aasset_get_buffer_original.unwrap()(handle);
             let asset_length = aasset_get_length_original.unwrap()(handle);
@@ -145,7 +160,7 @@ def_hook!(
 );
 
 def_hook!(
-    aasset_close,
+    always aasset_close,
     c_void,
     |handle: *mut c_void| {
         AASSET_MAP.lock().unwrap().remove(&(handle as usize));
@@ -222,36 +237,73 @@ pub unsafe fn composer_eval(env: JNIEnv, _: *mut c_void, script: JString) -> job
         return env.new_string(result).unwrap().into_raw()
     }
 
-    return env.new_string("Architecture not supported").unwrap().into_raw();
+    env.new_string("Architecture not supported").unwrap().into_raw()
 }
 
-pub fn init() {
-    if !config::native_config().composer_hooks {
-        return
+pub struct ComposerHook;
+
+impl NativeModule for ComposerHook {
+    fn name(&self) -> &'static str {
+        "composer"
     }
 
-    dobby_hook_sym!("libandroid.so", "AAsset_getBuffer", aasset_get_buffer);
-    dobby_hook_sym!("libandroid.so", "AAsset_getLength", aasset_get_length);
-    dobby_hook_sym!("libandroid.so", "AAsset_close", aasset_close);
-    dobby_hook_sym!("libandroid.so", "AAssetManager_open", aasset_manager_open);
-    
-    #[cfg(target_arch = "aarch64")]
-    {
-        if let Some(signature) = sig::find_signature(
-            &common::CLIENT_MODULE,
-            "00 E4 00 6F 29 00 80 52 76 00 04 8B", -0x28,
-            "A1 B0 07 92 81 46", -0x7
-        ) {
-            dobby_hook!(signature as *mut c_void, js_eval);
-            
-            unsafe { 
+    fn phase(&self) -> Phase {
+        Phase::Init
+    }
+
+    fn config_keys(&self) -> &'static [ConfigKey] {
+        &[ConfigKey::ComposerHooks]
+    }
+
+    fn init(&self, _: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+        // AAssetManager_open reads the assets through the other originals, it is only hooked once they are
+        dobby_hook_sym!("libandroid.so", "AAsset_getBuffer", aasset_get_buffer)?;
+        dobby_hook_sym!("libandroid.so", "AAsset_getLength", aasset_get_length)?;
+        dobby_hook_sym!("libandroid.so", "AAsset_close", aasset_close)?;
+        dobby_hook_sym!("libandroid.so", "AAssetManager_open", aasset_manager_open)?;
+        Ok(())
+    }
+}
+
+pub struct JsEvalHook;
+
+impl NativeModule for JsEvalHook {
+    fn name(&self) -> &'static str {
+        "js_eval"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Deferred(&target::CLIENT)
+    }
+
+    fn config_keys(&self) -> &'static [ConfigKey] {
+        &[ConfigKey::ComposerHooks]
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["composer"]
+    }
+
+    fn signatures(&self) -> &'static [&'static SignatureDef] {
+        &[&JS_EVAL_SIGNATURE]
+    }
+
+    #[allow(unreachable_code, unused_variables)]
+    fn init(&self, client_module: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+        #[cfg(target_arch = "aarch64")]
+        {
+            let client_module = client_module.ok_or("libclient.so is not mapped")?;
+
+            dobby_hook_sig!(client_module, &JS_EVAL_SIGNATURE, js_eval)?;
+
+            #[allow(clippy::missing_transmute_annotations)]
+            unsafe {
                 JS_EVAL_ORIGINAL2 = Some(std::mem::transmute(js_eval_original.unwrap()));
             }
-    
-            debug!("js_eval {:#x}", signature);
-        } else {
-            warn!("Unable to find js_eval signature");
+
+            return Ok(());
         }
+
+        Err("composerEval is only supported on arm64".into())
     }
 }
-
@@ -1,18 +1,21 @@
-use std::{ffi::CStr, fs};
+use std::{error::Error, ffi::CStr, fs};
 
 use nix::libc::{self, c_uint};
 
-use crate::{config, def_hook, dobby_hook_sym};
+use crate::{config::{self, ConfigKey}, def_hook, dobby_hook_sym, mapped_lib::MappedLib};
 
+use super::{NativeModule, Phase};
+
+// metadata and openat may go through the hooked open
 def_hook!(
-    open_hook,
+    guarded open_hook,
     i32,
     |path: *const u8, flags: i32, mode: c_uint| {
-        if let Ok(pathname) = CStr::from_ptr(path).to_str() {
+        if let Ok(pathname) = CStr::from_ptr(path as *const _).to_str() {
             if pathname == "/system/fonts/NotoColorEmoji.ttf"  {
                 if let Some(font_path) = config::native_config().custom_emoji_font_path {
                     if fs::metadata(&font_path).is_ok() {
-                        return libc::openat(libc::AT_FDCWD, font_path.as_ptr() as *const u8, flags, mode);
+                        return libc::openat(libc::AT_FDCWD, font_path.as_ptr() as *const _, flags, mode);
                     } else {
                         warn!("custom emoji font path does not exist: {}", font_path);
                     }
@@ -25,10 +28,23 @@ def_hook!(
 );
 
 
-pub fn init() {
-    if config::native_config().custom_emoji_font_path.is_none() {
-        return;
+pub struct CustomFontHook;
+
+impl NativeModule for CustomFontHook {
+    fn name(&self) -> &'static str {
+        "custom_font"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::PreInit
     }
 
-    dobby_hook_sym!("libc.so", "open", open_hook);
+    fn config_keys(&self) -> &'static [ConfigKey] {
+        &[ConfigKey::CustomEmojiFontPath]
+    }
+
+    fn init(&self, _: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+        dobby_hook_sym!("libc.so", "open", open_hook)?;
+        Ok(())
+    }
 }
\ No newline at end of file
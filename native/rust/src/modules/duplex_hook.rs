@@ -1,8 +1,10 @@
-use std::ffi::c_void;
+use std::{error::Error, ffi::c_void};
 
 use jni::{objects::JObject, sys::jboolean, JNIEnv};
 
-use crate::{common, def_hook, dobby_hook, util::get_jni_string};
+use crate::{common, def_hook, dobby_hook, hook::Resolution, mapped_lib::MappedLib, util::get_jni_string};
+
+use super::{NativeModule, Phase};
 
 
 def_hook!(
@@ -34,8 +36,25 @@ def_hook!(
 );
 
 
-pub fn init() {
-    common::attach_jni_env(|env| {
-        dobby_hook!((**env.get_native_interface()).IsSameObject.unwrap() as *mut c_void, is_same_object);
-    });
+pub struct DuplexHook;
+
+impl NativeModule for DuplexHook {
+    fn name(&self) -> &'static str {
+        "duplex"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Init
+    }
+
+    fn init(&self, _: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+        let mut result = Ok(());
+
+        common::attach_jni_env(|env| {
+            let is_same_object_ptr = unsafe { (**env.get_native_interface()).IsSameObject.unwrap() };
+            result = dobby_hook!("libart.so", Resolution::Pointer, is_same_object_ptr as *mut c_void, is_same_object);
+        });
+
+        Ok(result?)
+    }
 }
\ No newline at end of file
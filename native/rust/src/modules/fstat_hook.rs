@@ -1,19 +1,22 @@
 
-use std::fs;
+use std::{error::Error, fs};
 
 use nix::libc;
 
-use crate::{config::{self, native_config}, def_hook, dobby_hook_sym};
+use crate::{config::{native_config, ConfigKey}, def_hook, dobby_hook_sym, mapped_lib::MappedLib};
 
+use super::{NativeModule, Phase};
+
+// read_link calls back into libc
 def_hook!(
-    fstat_hook,
+    guarded fstat_hook,
     i32, 
     |fd: i32, statbuf: *mut libc::stat| {
         if let Ok(link) = fs::read_link("/proc/self/fd/".to_owned() + &fd.to_string()) {
             if let Some(filename) = link.file_name().map(|t| t.to_string_lossy()) {
                 let config = native_config();
                 if config.disable_metrics && filename.contains("files/blizzardv2/queues") {
-                    if libc::unlink((filename.to_owned() + "\0").as_ptr()) == -1 {
+                    if libc::unlink((filename.to_string() + "\0").as_ptr() as *const _) == -1 {
                         warn!("Failed to unlink {}", filename);
                     }
                     return -1;
@@ -29,9 +32,23 @@ def_hook!(
     }
 );
 
-pub fn init() {
-    let config = config::native_config();
-    if config.disable_metrics || config.disable_bitmoji {
-        dobby_hook_sym!("libc.so", "fstat", fstat_hook);
+pub struct FstatHook;
+
+impl NativeModule for FstatHook {
+    fn name(&self) -> &'static str {
+        "fstat"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::PreInit
+    }
+
+    fn config_keys(&self) -> &'static [ConfigKey] {
+        &[ConfigKey::DisableMetrics, ConfigKey::DisableBitmoji]
+    }
+
+    fn init(&self, _: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+        dobby_hook_sym!("libc.so", "fstat", fstat_hook)?;
+        Ok(())
     }
 }
\ No newline at end of file
@@ -1,21 +1,26 @@
-use std::{collections::HashMap, ffi::{c_void, CStr}, sync::Mutex};
+use std::{collections::HashMap, error::Error, ffi::{c_void, CStr}, sync::Mutex};
 
 use jni::{objects::{JByteArray, JString}, JNIEnv};
 use nix::libc;
 use once_cell::sync::Lazy;
 
-use crate::{def_hook, dobby_hook_sym};
+use crate::{deferred, def_hook, dobby_hook, dobby_hook_sym, hook::{HookError, Resolution}, mapped_lib::MappedLib};
 
-static SHARED_LIBRARIES: Lazy<Mutex<HashMap<String, Box<Vec<i8>>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+use super::{NativeModule, Phase};
+
+// do_dlopen handles both dlopen and android_dlopen_ext, its mangled name changed in android 8
+const DO_DLOPEN_SYMBOLS: &[&str] = &["__dl__Z9do_dlopenPKciPK17android_dlextinfoPKv", "__dl__Z9do_dlopenPKciPK17android_dlextinfoPv"];
+
+static SHARED_LIBRARIES: Lazy<Mutex<HashMap<String, Vec<i8>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 def_hook!(
     linker_openat,
     i32,
     |dir_fd: i32, pathname: *mut u8, flags: i32, mode: i32| {
-        let pathname_str = CStr::from_ptr(pathname).to_str().unwrap().to_string();
+        let pathname_str = CStr::from_ptr(pathname as *const _).to_str().unwrap().to_string();
 
         if let Some(content) = SHARED_LIBRARIES.lock().unwrap().remove(&pathname_str) {
-            let memfd = libc::syscall(libc::SYS_memfd_create, "jit-cache\0".as_ptr(), 0) as i32;
+            let memfd = libc::syscall(libc::SYS_memfd_create, c"jit-cache".as_ptr(), 0) as i32;
             let content = content.into_boxed_slice();
             
             if libc::write(memfd, content.as_ptr() as *const c_void, content.len() as libc::size_t) == -1 {
@@ -36,10 +41,26 @@ def_hook!(
     }
 );
 
+// the loaded library is handed to the deferred installer thread, its hooks may land after dlopen returns
+// and the first calls into the library can still reach the original functions
+def_hook!(
+    linker_do_dlopen,
+    *mut c_void,
+    |name: *const libc::c_char, flags: i32, extinfo: *const c_void, caller_addr: *const c_void| {
+        let handle = linker_do_dlopen_original.unwrap()(name, flags, extinfo, caller_addr);
+
+        if !handle.is_null() && !name.is_null() {
+            deferred::on_library_loaded(&CStr::from_ptr(name).to_string_lossy());
+        }
+
+        handle
+    }
+);
+
 pub fn add_linker_shared_library(mut env: JNIEnv, _: *mut c_void, path: JString, content: JByteArray) {
     let path = env.get_string(&path).unwrap().to_str().unwrap().to_string();
     let content_length = env.get_array_length(&content).expect("Failed to get array length");
-    let mut content_buffer = Box::new(vec![0i8; content_length as usize]);
+    let mut content_buffer = vec![0i8; content_length as usize];
     
     env.get_byte_array_region(content, 0, content_buffer.as_mut_slice()).expect("Failed to get byte array region");
     
@@ -48,13 +69,43 @@ pub fn add_linker_shared_library(mut env: JNIEnv, _: *mut c_void, path: JString,
     SHARED_LIBRARIES.lock().unwrap().insert(path, content_buffer);
 }
 
-pub fn init() {
-    #[cfg(target_arch = "aarch64")]
-    {
-        dobby_hook_sym!("linker64", "__dl___openat", linker_openat);
+// the bionic linker of the current arch, other platforms load libraries without it
+const fn linker_name() -> Option<&'static str> {
+    if !cfg!(target_os = "android") {
+        None
+    } else if cfg!(any(target_arch = "aarch64", target_arch = "x86_64")) {
+        Some("linker64")
+    } else if cfg!(any(target_arch = "arm", target_arch = "x86")) {
+        Some("linker")
+    } else {
+        None
+    }
+}
+
+pub struct LinkerHook;
+
+impl NativeModule for LinkerHook {
+    fn name(&self) -> &'static str {
+        "linker"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::PreInit
     }
-    #[cfg(target_arch = "arm")]
-    {
-        dobby_hook_sym!("linker", "__dl___openat", linker_openat);
+
+    fn init(&self, _: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+        let linker = linker_name().ok_or("No linker to hook on this platform")?;
+
+        dobby_hook_sym!(linker, "__dl___openat", linker_openat)?;
+
+        let result = match DO_DLOPEN_SYMBOLS.iter().find_map(|symbol| dobby_rs::resolve_symbol(linker, symbol)) {
+            Some(do_dlopen) => dobby_hook!(linker, Resolution::Symbol, do_dlopen, linker_do_dlopen),
+            None => Err(HookError::SymbolNotFound { library: linker.to_string(), symbol: DO_DLOPEN_SYMBOLS[0].to_string() }),
+        };
+
+        if let Err(error) = result {
+            warn!("{}, hooks of libraries loaded after init won't be installed", error);
+        }
+        Ok(())
     }
 }
@@ -5,4 +5,358 @@ pub mod sqlite_hook;
 pub mod fstat_hook;
 pub mod unary_call_hook;
 pub mod composer_hook;
-pub mod custom_font_hook;
\ No newline at end of file
+pub mod custom_font_hook;
+
+use std::{error::Error, panic::{self, AssertUnwindSafe}, sync::Mutex, thread::{self, JoinHandle}};
+
+use once_cell::sync::Lazy;
+
+use crate::{config::{self, ConfigKey, NativeConfig}, deferred, hook, mapped_lib::MappedLib, sig::SignatureDef, target::TargetModule};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Phase {
+    // from preInit, before the app classes are loaded
+    PreInit,
+    // on its own thread during init
+    Init,
+    // once the target module is mapped
+    Deferred(&'static TargetModule),
+}
+
+pub trait NativeModule: Sync {
+    fn name(&self) -> &'static str;
+
+    fn phase(&self) -> Phase;
+
+    // the module only runs when one of these is enabled, always when empty
+    fn config_keys(&self) -> &'static [ConfigKey] {
+        &[]
+    }
+
+    // modules that must be initialized before this one
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // signatures resolved by the module, they are prefetched before the deferred modules run
+    fn signatures(&self) -> &'static [&'static SignatureDef] {
+        &[]
+    }
+
+    // a failing module is shut down so none of its hooks stay installed. deferred modules get their mapped target module
+    fn init(&self, target_module: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>>;
+
+    // removes the hooks installed by the init of the module, it may be initialized again afterward
+    fn shutdown(&self) {
+        hook::unhook_module(self.name());
+    }
+}
+
+// adding a native feature only takes an entry here
+pub static MODULES: &[&dyn NativeModule] = &[
+    &linker_hook::LinkerHook,
+    &custom_font_hook::CustomFontHook,
+    &fstat_hook::FstatHook,
+    &duplex_hook::DuplexHook,
+    &composer_hook::ComposerHook,
+    &unary_call_hook::UnaryCallHook,
+    &sqlite_hook::SqliteHook,
+    &composer_hook::JsEvalHook,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleState {
+    // none of its config keys is enabled
+    Disabled,
+    Pending,
+    Initialized,
+    Failed(String),
+}
+
+static MODULE_STATES: Mutex<Vec<(&'static str, ModuleState)>> = Mutex::new(Vec::new());
+
+fn set_state(name: &'static str, state: ModuleState) {
+    if let ModuleState::Failed(error) = &state {
+        warn!("Module {} failed: {}", name, error);
+    }
+
+    let mut states = MODULE_STATES.lock().unwrap();
+    match states.iter_mut().find(|(module, _)| *module == name) {
+        Some((_, current)) => *current = state,
+        None => states.push((name, state)),
+    }
+}
+
+pub fn module_state(name: &str) -> Option<ModuleState> {
+    MODULE_STATES.lock().unwrap().iter().find(|(module, _)| *module == name).map(|(_, state)| state.clone())
+}
+
+/*
+ * orders the modules so each one comes after its dependencies, keeping the registry order otherwise
+ * - modules depending on an unknown module are left out
+ * - modules of a dependency cycle are left out
+ */
+fn order_modules(modules: &[&'static dyn NativeModule]) -> (Vec<&'static dyn NativeModule>, Vec<(&'static str, String)>) {
+    let mut ordered: Vec<&'static dyn NativeModule> = Vec::with_capacity(modules.len());
+    let mut errors = Vec::new();
+    let mut remaining = Vec::new();
+
+    for module in modules {
+        match module.dependencies().iter().find(|dependency| !modules.iter().any(|module| module.name() == **dependency)) {
+            Some(dependency) => errors.push((module.name(), format!("unknown dependency {}", dependency))),
+            None => remaining.push(*module),
+        }
+    }
+
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|module| {
+            module.dependencies().iter().all(|dependency| ordered.iter().any(|ordered| ordered.name() == *dependency))
+        });
+
+        match ready {
+            Some(index) => ordered.push(remaining.remove(index)),
+            None => errors.extend(remaining.drain(..).map(|module| (module.name(), "dependency cycle".to_string()))),
+        }
+    }
+
+    (ordered, errors)
+}
+
+static ORDERED_MODULES: Lazy<Vec<&'static dyn NativeModule>> = Lazy::new(|| {
+    let (ordered, errors) = order_modules(MODULES);
+    errors.into_iter().for_each(|(name, error)| set_state(name, ModuleState::Failed(error)));
+    ordered
+});
+
+fn enabled_by(module: &dyn NativeModule, config: &NativeConfig) -> bool {
+    module.config_keys().is_empty() || module.config_keys().iter().any(|key| config.is_enabled(*key))
+}
+
+// disabled modules are not run
+fn is_enabled(module: &dyn NativeModule) -> bool {
+    let enabled = enabled_by(module, &config::native_config());
+
+    if !enabled {
+        set_state(module.name(), ModuleState::Disabled);
+    }
+    enabled
+}
+
+// checks the dependencies then initializes the module, its state follows the result
+fn run(module: &dyn NativeModule, target_module: Option<&'static MappedLib>) -> Result<(), String> {
+    let result = match module.dependencies().iter().find(|dependency| module_state(dependency) != Some(ModuleState::Initialized)) {
+        Some(dependency) => Err(format!("dependency {} is not initialized", dependency)),
+        // a panicking module must not take down the modules initialized after it, the crate is built with panic = "unwind"
+        None => match panic::catch_unwind(AssertUnwindSafe(|| hook::install_for(module.name(), || module.init(target_module)))) {
+            Ok(result) => result.map_err(|error| error.to_string()),
+            Err(_) => Err("panicked during init".to_string()),
+        },
+    };
+
+    if result.is_err() {
+        module.shutdown();
+    }
+
+    set_state(module.name(), match &result {
+        Ok(()) => ModuleState::Initialized,
+        Err(error) => ModuleState::Failed(error.clone()),
+    });
+    result
+}
+
+fn modules_of(phase: fn(Phase) -> bool) -> impl Iterator<Item = &'static dyn NativeModule> {
+    ORDERED_MODULES.iter().copied().filter(move |module| phase(module.phase()))
+}
+
+pub fn pre_init() {
+    for module in modules_of(|phase| matches!(phase, Phase::PreInit)).filter(|module| is_enabled(*module)) {
+        let _ = run(module, None);
+    }
+}
+
+// init modules run in parallel, a module waits for the running ones when it depends on them
+pub fn init() {
+    let mut running: Vec<(&'static str, JoinHandle<()>)> = Vec::new();
+
+    fn join(running: &mut Vec<(&'static str, JoinHandle<()>)>) {
+        for (name, thread) in running.drain(..) {
+            if thread.join().is_err() {
+                set_state(name, ModuleState::Failed("panicked during init".to_string()));
+            }
+        }
+    }
+
+    for module in modules_of(|phase| matches!(phase, Phase::Init)).filter(|module| is_enabled(*module)) {
+        if module.dependencies().iter().any(|dependency| running.iter().any(|(name, _)| name == dependency)) {
+            join(&mut running);
+        }
+
+        set_state(module.name(), ModuleState::Pending);
+        running.push((module.name(), thread::spawn(move || {
+            let _ = run(module, None);
+        })));
+    }
+
+    join(&mut running);
+}
+
+// the dependencies of deferred modules are checked once their target module is loaded
+pub fn defer() {
+    for module in modules_of(|phase| matches!(phase, Phase::Deferred(_))).filter(|module| is_enabled(*module)) {
+        let Phase::Deferred(target) = module.phase() else {
+            continue;
+        };
+
+        defer_module(module, target);
+    }
+}
+
+fn defer_module(module: &'static dyn NativeModule, target: &TargetModule) {
+    set_state(module.name(), ModuleState::Pending);
+    deferred::defer(module.name(), target, move |target_module| run(module, Some(target_module)));
+}
+
+fn disable(module: &dyn NativeModule) {
+    match module_state(module.name()) {
+        Some(ModuleState::Initialized) => {
+            debug!("Shutting down {}", module.name());
+            module.shutdown();
+        }
+        // only deferred modules wait for their installation
+        Some(ModuleState::Pending) if matches!(module.phase(), Phase::Deferred(_)) => {}
+        _ => return,
+    }
+
+    if matches!(module.phase(), Phase::Deferred(_)) {
+        deferred::cancel(module.name());
+    }
+    set_state(module.name(), ModuleState::Disabled);
+}
+
+fn enable(module: &'static dyn NativeModule) {
+    debug!("Enabling {}", module.name());
+
+    match module.phase() {
+        Phase::PreInit | Phase::Init => {
+            let _ = run(module, None);
+        }
+        Phase::Deferred(target) => defer_module(module, target),
+    }
+}
+
+/*
+ * installs or removes the modules whose config keys changed
+ * - modules turned off are shut down, dependents first
+ * - modules turned on are initialized again, only once their phase already ran
+ * hooks read the config on each call, changes of the enabled modules need nothing else
+ */
+pub fn apply_config(previous: &NativeConfig, config: &NativeConfig) {
+    for module in ORDERED_MODULES.iter().rev().filter(|module| enabled_by(**module, previous) && !enabled_by(**module, config)) {
+        disable(*module);
+    }
+
+    for module in ORDERED_MODULES.iter().filter(|module| !enabled_by(**module, previous) && enabled_by(**module, config)) {
+        if module_state(module.name()) == Some(ModuleState::Disabled) {
+            enable(*module);
+        }
+    }
+}
+
+// signatures of the enabled modules
+pub fn enabled_signatures() -> Vec<&'static SignatureDef> {
+    MODULES.iter().filter(|module| is_enabled(**module)).flat_map(|module| module.signatures().iter().copied()).collect()
+}
+
+// shuts down the initialized modules, dependents first
+pub fn shutdown() {
+    ORDERED_MODULES.iter().rev().for_each(|module| disable(*module));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    struct TestModule(&'static str, &'static [&'static str]);
+
+    impl NativeModule for TestModule {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn phase(&self) -> Phase {
+            Phase::Init
+        }
+
+        fn dependencies(&self) -> &'static [&'static str] {
+            self.1
+        }
+
+        fn init(&self, _: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+    }
+
+    fn names(modules: &[&dyn NativeModule]) -> Vec<&'static str> {
+        modules.iter().map(|module| module.name()).collect()
+    }
+
+    #[test]
+    fn orders_modules_after_their_dependencies() {
+        let modules: &[&'static dyn NativeModule] = &[&TestModule("js_eval", &["composer"]), &TestModule("linker", &[]), &TestModule("composer", &["linker"])];
+
+        assert_eq!(names(&order_modules(modules).0), ["linker", "composer", "js_eval"]);
+        assert_eq!(order_modules(MODULES).0.len(), MODULES.len());
+    }
+
+    #[test]
+    fn rejects_unknown_and_cyclic_dependencies() {
+        let modules: &[&'static dyn NativeModule] = &[&TestModule("a", &["b"]), &TestModule("b", &["a"]), &TestModule("c", &["missing"]), &TestModule("d", &[])];
+        let (ordered, errors) = order_modules(modules);
+
+        assert_eq!(names(&ordered), ["d"]);
+        assert_eq!(errors.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["c", "a", "b"]);
+        assert_eq!(errors[0].1, "unknown dependency missing");
+    }
+
+    #[test]
+    fn enables_modules_from_any_config_key() {
+        let mut config = NativeConfig { disable_bitmoji: false, disable_metrics: false, composer_hooks: false, custom_emoji_font_path: None, signature_scan_budget: None };
+        assert!(!enabled_by(&fstat_hook::FstatHook, &config));
+        assert!(enabled_by(&linker_hook::LinkerHook, &config));
+
+        config.disable_bitmoji = true;
+        assert!(enabled_by(&fstat_hook::FstatHook, &config));
+        assert!(!enabled_by(&custom_font_hook::CustomFontHook, &config));
+    }
+
+    struct PanickingModule(AtomicBool);
+
+    impl NativeModule for PanickingModule {
+        fn name(&self) -> &'static str {
+            "panicking"
+        }
+
+        fn phase(&self) -> Phase {
+            Phase::PreInit
+        }
+
+        fn init(&self, _: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+            panic!("symbol not found");
+        }
+
+        fn shutdown(&self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn shuts_down_failing_modules() {
+        let module = PanickingModule(AtomicBool::new(false));
+
+        assert_eq!(run(&module, None), Err("panicked during init".to_string()));
+        assert_eq!(module_state("panicking"), Some(ModuleState::Failed("panicked during init".into())));
+        assert!(module.0.load(Ordering::SeqCst));
+    }
+}
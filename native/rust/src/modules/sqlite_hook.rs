@@ -1,10 +1,20 @@
-use std::{collections::HashMap, ffi::{c_void, CStr}, mem::size_of, ptr::addr_of_mut, sync::Mutex};
+use std::{collections::HashMap, error::Error, ffi::{c_void, CStr}, mem::size_of, ptr::addr_of_mut, sync::Mutex};
 
 use jni::{objects::{JObject, JString}, JNIEnv};
 use nix::libc::{self, pthread_mutex_t};
 use once_cell::sync::Lazy;
 
-use crate::{common, def_hook, dobby_hook, sig, util::get_jni_string};
+use crate::{def_hook, dobby_hook_sig, mapped_lib::MappedLib, sig::{Candidate, SignatureDef}, target, util::get_jni_string};
+
+use super::{NativeModule, Phase};
+
+pub const SQLITE3_OPEN_SIGNATURE: SignatureDef = SignatureDef {
+    name: "sqlite3_open",
+    candidates: &[
+        Candidate::arm64("FF FF 00 A9 3F 00 00 F9", -0x3C),
+        Candidate::arm("9A 46 90 46 78 44 89 46 05 68", -0xd),
+    ],
+};
 
 
 #[repr(C)]
@@ -30,8 +40,8 @@ def_hook!(
         if result == 0 {
             let sqlite3_mutex = (**pp_db).mutex;
 
-            if sqlite3_mutex != std::ptr::null_mut() {
-                let filename = CStr::from_ptr(filename).to_string_lossy().to_string().split("/").last().expect("Failed to get filename").to_string();
+            if !sqlite3_mutex.is_null() {
+                let filename = CStr::from_ptr(filename as *const _).to_string_lossy().split("/").last().expect("Failed to get filename").to_string();
                 debug!("sqlite3_open hook {:?}", filename);
 
                 SQLITE3_MUTEX_MAP.lock().unwrap().insert(
@@ -48,7 +58,7 @@ def_hook!(
 
 pub fn lock_database(mut env: JNIEnv, _: *mut c_void, filename: JString, runnable: JObject) {
     let database_filename = get_jni_string(&mut env, filename).expect("Failed to get database filename");
-    let mutex = SQLITE3_MUTEX_MAP.lock().unwrap().get(&database_filename).map(|mutex| *mutex);
+    let mutex = SQLITE3_MUTEX_MAP.lock().unwrap().get(&database_filename).copied();
 
     if let Some(mut mutex) = mutex {
         if unsafe { libc::pthread_mutex_lock(addr_of_mut!(mutex)) } != 0 {
@@ -67,15 +77,25 @@ pub fn lock_database(mut env: JNIEnv, _: *mut c_void, filename: JString, runnabl
 }
 
 
-pub fn init() {
-    if let Some(signature) = sig::find_signature(
-        &common::CLIENT_MODULE, 
-        "FF FF 00 A9 3F 00 00 F9", -0x3C,
-        "9A 46 90 46 78 44 89 46 05 68",-0xd
-    ) {
-        debug!("Found sqlite3_open signature: {:#x}", signature);
-        dobby_hook!(signature as *mut c_void, sqlite3_open);
-    } else {
-        warn!("Failed to find sqlite3_open signature");
+pub struct SqliteHook;
+
+impl NativeModule for SqliteHook {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Deferred(&target::CLIENT)
+    }
+
+    fn signatures(&self) -> &'static [&'static SignatureDef] {
+        &[&SQLITE3_OPEN_SIGNATURE]
+    }
+
+    fn init(&self, client_module: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+        let client_module = client_module.ok_or("libclient.so is not mapped")?;
+
+        dobby_hook_sig!(client_module, &SQLITE3_OPEN_SIGNATURE, sqlite3_open)?;
+        Ok(())
     }
 }
\ No newline at end of file
@@ -1,10 +1,20 @@
-use std::ffi::{c_void, CStr};
+use std::{error::Error, ffi::{c_void, CStr}};
 
 use jni::{objects::{JByteArray, JMethodID, JValue}, signature::ReturnType};
 use nix::libc;
 use once_cell::sync::OnceCell;
 
-use crate::{common::{self}, def_hook, dobby_hook, sig};
+use crate::{common::{self}, def_hook, dobby_hook_sig, mapped_lib::MappedLib, sig::{Candidate, SignatureDef}, target};
+
+use super::{NativeModule, Phase};
+
+pub const UNARY_CALL_SIGNATURE: SignatureDef = SignatureDef {
+    name: "unary_call",
+    candidates: &[
+        Candidate::arm64("A8 03 1F F8 C2 00 00 94", -0x48),
+        Candidate::arm("0A 90 00 F0 3F F9", -0x37),
+    ],
+};
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -24,8 +34,9 @@ struct GrpcByteBuffer {
 
 static NATIVE_LIB_ON_UNARY_CALL_METHOD: OnceCell<JMethodID> = OnceCell::new();
 
+// the java callback can send requests of its own
 def_hook!(
-    unary_call,
+    guarded unary_call,
     *mut c_void,
     |unk1: *mut c_void, uri: *const u8, grpc_byte_buffer: *mut *mut GrpcByteBuffer, unk4: *mut c_void, unk5: *mut c_void, unk6: *mut c_void| {
         macro_rules! call_original {
@@ -44,11 +55,11 @@ def_hook!(
         let java_vm = common::java_vm();
         let mut env = java_vm.get_env().expect("Failed to get JNIEnv");
 
-        let slice_buffer_length = slice_buffer.length as usize;
+        let slice_buffer_length = slice_buffer.length;
         let jni_buffer = env.new_byte_array(slice_buffer_length as i32).expect("Failed to create new byte array");
         env.set_byte_array_region(&jni_buffer, 0, std::slice::from_raw_parts(slice_buffer.data as *const i8, slice_buffer_length)).expect("Failed to set byte array region");
 
-        let uri_str = CStr::from_ptr(uri).to_str().unwrap();
+        let uri_str = CStr::from_ptr(uri as *const _).to_str().unwrap();
 
         let native_request_data_object = env.call_method_unchecked(
             common::native_lib_instance(),
@@ -81,16 +92,16 @@ def_hook!(
 
         //we need to allocate a new ref_counter struct and copy the old ref_counter and the new_buffer to it
         let new_ref = {
-            let new_ref = libc::malloc(ref_counter_struct_size + new_buffer_length) as *mut c_void;
+            let new_ref = libc::malloc(ref_counter_struct_size + new_buffer_length);
             libc::memcpy(new_ref, slice_buffer.ref_counter, ref_counter_struct_size);
-            libc::memcpy(new_ref.offset(ref_counter_struct_size as isize), new_buffer_data.as_ptr() as *const c_void, new_buffer_length);
+            libc::memcpy(new_ref.add(ref_counter_struct_size), new_buffer_data.as_ptr() as *const c_void, new_buffer_length);
             libc::free(slice_buffer.ref_counter);
             new_ref
         };
 
         slice_buffer.ref_counter = new_ref;
         slice_buffer.length = new_buffer_length;
-        slice_buffer.data = new_ref.offset(ref_counter_struct_size as isize) as *mut u8;
+        slice_buffer.data = new_ref.add(ref_counter_struct_size) as *mut u8;
 
         // update the grpc byte buffer
         *(**grpc_byte_buffer).slice_buffer = slice_buffer;
@@ -101,23 +112,38 @@ def_hook!(
     }
 );
 
-pub fn init() {
-    if let Some(signature) = sig::find_signature(
-        &common::CLIENT_MODULE, 
-        "A8 03 1F F8 C2 00 00 94", -0x48,
-        "0A 90 00 F0 3F F9", -0x37
-    ) {
-        dobby_hook!(signature as *mut c_void, unary_call);
+pub struct UnaryCallHook;
+
+impl NativeModule for UnaryCallHook {
+    fn name(&self) -> &'static str {
+        "unary_call"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Deferred(&target::CLIENT)
+    }
+
+    fn signatures(&self) -> &'static [&'static SignatureDef] {
+        &[&UNARY_CALL_SIGNATURE]
+    }
+
+    fn init(&self, client_module: Option<&'static MappedLib>) -> Result<(), Box<dyn Error>> {
+        let client_module = client_module.ok_or("libclient.so is not mapped")?;
+
+        // the hook calls the method as soon as it is installed, a module initialized again keeps the resolved id
+        let mut method = Ok(());
         common::attach_jni_env(|env| {
-            NATIVE_LIB_ON_UNARY_CALL_METHOD.set(
+            method = NATIVE_LIB_ON_UNARY_CALL_METHOD.get_or_try_init(|| {
                 env.get_method_id(
-                    env.get_object_class(common::native_lib_instance()).unwrap(),
-                        "onNativeUnaryCall", 
-                        "(Ljava/lang/String;[B)Lme/rhunk/snapenhance/nativelib/NativeRequestData;"
-                ).expect("Failed to get onNativeUnaryCall method id")
-            ).expect("unary call method already set");
+                    env.get_object_class(common::native_lib_instance())?,
+                    "onNativeUnaryCall",
+                    "(Ljava/lang/String;[B)Lme/rhunk/snapenhance/nativelib/NativeRequestData;"
+                )
+            }).map(|_| ());
         });
-    } else {
-        error!("Can't find unaryCall signature");
+        method?;
+
+        dobby_hook_sig!(client_module, &UNARY_CALL_SIGNATURE, unary_call)?;
+        Ok(())
     }
 }
\ No newline at end of file
@@ -15,7 +15,7 @@ impl ModuleTag {
     }
 
     pub fn to_string(&self) -> Result<String, FromUtf8Error> {
-        Ok(String::from_utf8(self.buffer.clone())?)
+        String::from_utf8(self.buffer.clone())
     }
 
     pub fn get_tag_type(&self) -> u8 {
@@ -61,7 +61,7 @@ impl ComposerModule {
                 break;
             }
 
-            fn read_u24(buffer: &Vec<u8>, offset: &mut usize) -> Result<u32, Error> {
+            fn read_u24(buffer: &[u8], offset: &mut usize) -> Result<u32, Error> {
                 let b1 = buffer[*offset] as u32;
                 let b2 = buffer[*offset + 1] as u32;
                 let b3 = buffer[*offset + 2] as u32;
@@ -0,0 +1,148 @@
+use std::{error::Error, fs, path::Path};
+
+use procfs::process::MMPermissions;
+
+use crate::{elf, mapped_lib::{MappedLib, MappedRegion}, sig::Arch, zip};
+
+const PAGE_SIZE: u64 = 0x1000;
+// the image is copied at this alignment so page relative instructions decode like on the device
+const IMAGE_ALIGNMENT: usize = 0x10000;
+
+fn page_start(value: u64) -> u64 {
+    value & !(PAGE_SIZE - 1)
+}
+
+fn page_end(value: u64) -> u64 {
+    page_start(value + PAGE_SIZE - 1)
+}
+
+/*
+ * library loaded from disk the way the bionic linker maps it
+ * - every PT_LOAD segment is copied at its virtual address into a single buffer
+ * - the regions cover the file backed pages of the segments, as listed in /proc/self/maps
+ */
+pub struct OfflineModule {
+    _buffer: Vec<u8>,
+    // address of the virtual address 0 of the library
+    pub base: usize,
+    pub arch: Option<Arch>,
+    pub mapped_lib: MappedLib,
+}
+
+impl OfflineModule {
+    // loads an ELF file, or the library with the given file name stored in an APK
+    pub fn load(path: &Path, library_name: &str) -> Result<Self, Box<dyn Error>> {
+        let file = fs::read(path)?;
+        let path_name = path.to_string_lossy().to_string();
+
+        if elf::is_elf(&file) {
+            return Self::from_image(path_name, 0, &file);
+        }
+
+        let entry = zip::zip_entries(&file)?.into_iter()
+            .find(|entry| entry.file_name() == library_name)
+            .ok_or_else(|| format!("{} not found in {}", library_name, path_name))?;
+
+        if !entry.stored {
+            return Err(format!("{} is compressed and can't be mapped from the archive", entry.name).into());
+        }
+
+        let image = file.get(entry.data_offset as usize..(entry.data_offset + entry.size) as usize).ok_or("Truncated archive entry")?;
+        Self::from_image(path_name, entry.data_offset, image)
+    }
+
+    // maps an ELF image located at the given offset of the file
+    pub fn from_image(path: String, file_offset: u64, image: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let segments = elf::program_headers(image).ok_or("Invalid ELF image")?.into_iter()
+            .filter(|header| header.p_type == elf::PT_LOAD)
+            .collect::<Vec<_>>();
+
+        let min_address = segments.iter().map(|segment| page_start(segment.p_vaddr)).min().ok_or("No loadable segment")?;
+        let max_address = segments.iter().map(|segment| page_end(segment.p_vaddr + segment.p_memsz)).max().unwrap_or(min_address);
+        let image_size = (max_address - min_address) as usize;
+
+        let mut buffer = vec![0u8; image_size + IMAGE_ALIGNMENT];
+        let padding = buffer.as_ptr().align_offset(IMAGE_ALIGNMENT);
+        let image_start = buffer.as_ptr() as usize + padding;
+        let mut mapped_lib = MappedLib::new(path.clone());
+        mapped_lib.file_size = Some(image.len() as u64);
+
+        for segment in &segments {
+            let address = page_start(segment.p_vaddr);
+            let file_start = page_start(segment.p_offset) as usize;
+            let file_end = (page_end(segment.p_offset + segment.p_filesz) as usize).min(image.len());
+            let destination = padding + (address - min_address) as usize;
+
+            if let Some(bytes) = image.get(file_start..file_end) {
+                buffer[destination..destination + bytes.len()].copy_from_slice(bytes);
+            }
+
+            let mut perms = MMPermissions::PRIVATE;
+            for (flag, permission) in [(elf::PF_R, MMPermissions::READ), (elf::PF_W, MMPermissions::WRITE), (elf::PF_X, MMPermissions::EXECUTE)] {
+                if segment.p_flags & flag != 0 {
+                    perms |= permission;
+                }
+            }
+
+            let start = image_start as u64 + address - min_address;
+            // the regions point into the buffer, which is kept alongside the library
+            let region = unsafe { MappedRegion::new(start, start + page_end(segment.p_vaddr + segment.p_filesz) - address, perms, file_offset + file_start as u64, path.clone()) };
+            mapped_lib.regions.push(region);
+        }
+
+        Ok(Self {
+            _buffer: buffer,
+            base: image_start - min_address as usize,
+            arch: elf::machine(image).and_then(Arch::from_elf_machine),
+            mapped_lib,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ELF64 with a read-only segment holding the headers and an executable segment
+    fn elf_image() -> Vec<u8> {
+        let mut image = vec![0u8; 0x1800];
+        image[..4].copy_from_slice(elf::ELF_MAGIC);
+        image[4] = 2;
+        image[5] = 1;
+        image[0x12..0x14].copy_from_slice(&elf::EM_AARCH64.to_le_bytes());
+        image[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+        image[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        image[0x38..0x3A].copy_from_slice(&2u16.to_le_bytes());
+
+        for (index, (flags, offset, address, size)) in [(elf::PF_R, 0u64, 0u64, 0x200u64), (elf::PF_R | elf::PF_X, 0x1000, 0x5000, 0x800)].into_iter().enumerate() {
+            let header = 64 + index * 56;
+            image[header..header + 4].copy_from_slice(&elf::PT_LOAD.to_le_bytes());
+            image[header + 4..header + 8].copy_from_slice(&flags.to_le_bytes());
+            image[header + 8..header + 0x10].copy_from_slice(&offset.to_le_bytes());
+            image[header + 0x10..header + 0x18].copy_from_slice(&address.to_le_bytes());
+            image[header + 0x20..header + 0x28].copy_from_slice(&size.to_le_bytes());
+            image[header + 0x28..header + 0x30].copy_from_slice(&size.to_le_bytes());
+        }
+
+        image[0x1000..0x1004].copy_from_slice(&[0xC0, 0x03, 0x5F, 0xD6]);
+        image
+    }
+
+    #[test]
+    fn maps_segments_at_their_address() {
+        let image = elf_image();
+        let module = OfflineModule::from_image("libclient.so".into(), 0x3000, &image).unwrap();
+        let regions = &module.mapped_lib.regions;
+
+        assert_eq!(module.arch, Some(Arch::Arm64));
+        assert_eq!(module.mapped_lib.load_bias(), Some(module.base));
+        assert_eq!(module.base % IMAGE_ALIGNMENT, 0);
+        assert_eq!(regions.len(), 2);
+        assert_eq!((regions[0].start as usize - module.base, regions[0].end - regions[0].start), (0, 0x1000));
+        assert_eq!((regions[1].start as usize - module.base, regions[1].end - regions[1].start), (0x5000, 0x1000));
+        assert_eq!(regions[1].offset, 0x4000);
+        assert!(regions[1].perms.contains(MMPermissions::EXECUTE) && !regions[0].perms.contains(MMPermissions::EXECUTE));
+
+        assert_eq!(&regions[1].bytes()[..4], &[0xC0, 0x03, 0x5F, 0xD6]);
+    }
+}
@@ -0,0 +1,103 @@
+// decodes the pc relative operands of the arm64 instructions signatures can reference
+
+use crate::memory::read_u32;
+
+fn sign_extend(value: u64, bits: u32) -> i64 {
+    ((value << (64 - bits)) as i64) >> (64 - bits)
+}
+
+// BL <label>
+pub fn decode_bl(insn: u32, pc: u64) -> Option<u64> {
+    if insn & 0xFC000000 != 0x94000000 {
+        return None;
+    }
+    Some(pc.wrapping_add_signed(sign_extend((insn & 0x03FFFFFF) as u64, 26) << 2))
+}
+
+// ADRP Xd, <page>, returns the destination register and the page address
+pub fn decode_adrp(insn: u32, pc: u64) -> Option<(u32, u64)> {
+    if insn & 0x9F000000 != 0x90000000 {
+        return None;
+    }
+    let immediate = ((insn >> 5) & 0x7FFFF) << 2 | (insn >> 29) & 0x3;
+    Some((insn & 0x1F, (pc & !0xFFF).wrapping_add_signed(sign_extend(immediate as u64, 21) << 12)))
+}
+
+// ADD Xd, Xn, #imm{, LSL #12}, returns the source register and the immediate
+pub fn decode_add_immediate(insn: u32) -> Option<(u32, u64)> {
+    if insn & 0xFF800000 != 0x91000000 {
+        return None;
+    }
+    let shift = if insn & (1 << 22) != 0 { 12 } else { 0 };
+    Some(((insn >> 5) & 0x1F, (((insn >> 10) & 0xFFF) as u64) << shift))
+}
+
+// LDR Xt, [Xn, #imm], returns the base register and the offset
+fn decode_ldr_immediate(insn: u32) -> Option<(u32, u64)> {
+    if insn & 0xFFC00000 != 0xF9400000 {
+        return None;
+    }
+    Some(((insn >> 5) & 0x1F, (((insn >> 10) & 0xFFF) as u64) << 3))
+}
+
+// follows the BL at the given offset of the code, pc is the address of the first byte of the code
+pub fn branch_target(code: &[u8], offset: usize, pc: u64) -> Option<u64> {
+    decode_bl(read_u32(code, offset)?, pc + offset as u64)
+}
+
+// resolves the address built by an ADRP followed by an ADD or LDR on the same register
+pub fn page_address(code: &[u8], offset: usize, pc: u64) -> Option<u64> {
+    let (register, page) = decode_adrp(read_u32(code, offset)?, pc + offset as u64)?;
+    let next = read_u32(code, offset + 4)?;
+
+    let (base, page_offset) = decode_add_immediate(next).or_else(|| decode_ldr_immediate(next))?;
+    (base == register).then(|| page + page_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig::fixtures::arm64;
+
+    #[test]
+    fn decodes_bl() {
+        assert_eq!(decode_bl(0x94000010, 0x1000), Some(0x1040)); // bl #0x40
+        assert_eq!(decode_bl(0x97FFFFFC, 0x1000), Some(0xFF0)); // bl #-0x10
+        assert_eq!(decode_bl(0x14000010, 0x1000), None); // b #0x40
+    }
+
+    #[test]
+    fn decodes_adrp() {
+        assert_eq!(decode_adrp(0xD0000000, 0x1234), Some((0, 0x3000))); // adrp x0, #0x2000
+        assert_eq!(decode_adrp(0xF0FFFFE1, 0x5678), Some((1, 0x4000))); // adrp x1, #-0x1000
+        assert_eq!(decode_adrp(0x10000000, 0x1234), None); // adr x0, #0
+    }
+
+    #[test]
+    fn follows_branch_in_code() {
+        let code = arm64(&[
+            0xAA0003F3, // mov x19, x0
+            0x94000010, // bl #0x40
+        ]);
+        assert_eq!(branch_target(&code, 4, 0x7000), Some(0x7044));
+        assert_eq!(branch_target(&code, 0, 0x7000), None);
+        assert_eq!(branch_target(&code, 8, 0x7000), None);
+    }
+
+    #[test]
+    fn resolves_page_address() {
+        let code = arm64(&[
+            0xD0000000, // adrp x0, #0x2000
+            0x91048C00, // add x0, x0, #0x123
+            0xF0FFFFE1, // adrp x1, #-0x1000
+            0xF9400821, // ldr x1, [x1, #0x10]
+            0xD0000000, // adrp x0, #0x2000
+            0x91048C21, // add x1, x1, #0x123
+        ]);
+        assert_eq!(page_address(&code, 0, 0x10000), Some(0x12123));
+        assert_eq!(page_address(&code, 8, 0x10000), Some(0xF010));
+        // the add does not use the adrp register
+        assert_eq!(page_address(&code, 16, 0x10000), None);
+        assert_eq!(page_address(&code, 4, 0x10000), None);
+    }
+}
@@ -0,0 +1,93 @@
+use std::error::Error;
+
+use procfs::process::MMPermissions;
+use serde::{Deserialize, Serialize};
+
+use crate::mapped_lib::MappedLib;
+
+// version 2: entries hold every match of the signature instead of the first one
+// version 3: offsets are virtual addresses of the module instead of offsets from its first executable region
+pub const CACHE_VERSION: u32 = 3;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// identifies the exact build of the module the signature offsets were computed for
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModuleIdentity {
+    pub build_id: Option<String>,
+    pub file_size: u64,
+    pub text_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub signature: String,
+    pub offsets: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignatureCache {
+    pub version: u32,
+    pub module: Option<ModuleIdentity>,
+    pub entries: Vec<CacheEntry>,
+}
+
+impl Default for SignatureCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignatureCache {
+    pub const fn new() -> Self {
+        Self {
+            version: CACHE_VERSION,
+            module: None,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, signature: &str) -> Option<&Vec<usize>> {
+        self.entries.iter().find(|entry| entry.signature == signature).map(|entry| &entry.offsets)
+    }
+
+    pub fn insert(&mut self, signature: String, offsets: Vec<usize>) {
+        self.remove(&signature);
+        self.entries.push(CacheEntry { signature, offsets });
+    }
+
+    pub fn remove(&mut self, signature: &str) {
+        self.entries.retain(|entry| entry.signature != signature);
+    }
+}
+
+// word sized FNV-1a, only used to tell builds apart
+fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    let mut chunks = bytes.chunks_exact(8);
+    let mut hash = chunks.by_ref().fold(hash, |hash, chunk| {
+        (hash ^ u64::from_le_bytes(chunk.try_into().unwrap())).wrapping_mul(FNV_PRIME)
+    });
+
+    for byte in chunks.remainder() {
+        hash = (hash ^ *byte as u64).wrapping_mul(FNV_PRIME);
+    }
+
+    hash
+}
+
+impl ModuleIdentity {
+    pub fn from_mapped_lib(mapped_lib: &MappedLib) -> Result<Self, Box<dyn Error>> {
+        let file_size = mapped_lib.file_size().ok_or("Unknown library file size")?;
+
+        let build_id = mapped_lib.build_id().map(|build_id| build_id.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+
+        let text_hash = mapped_lib.regions.iter().filter(|region| region.perms.contains(MMPermissions::EXECUTE)).fold(FNV_OFFSET_BASIS, |hash, region| hash_bytes(hash, region.bytes()));
+
+        Ok(Self {
+            build_id,
+            file_size,
+            text_hash: format!("{:016x}", text_hash),
+        })
+    }
+}
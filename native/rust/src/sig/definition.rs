@@ -0,0 +1,284 @@
+use std::{cmp::Ordering, fmt, str::FromStr, sync::Mutex};
+
+use serde::Deserialize;
+
+use crate::elf;
+
+use super::overrides;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Arch {
+    Arm64,
+    Arm,
+    X86_64,
+    X86,
+}
+
+impl Arch {
+    pub const fn current() -> Option<Arch> {
+        if cfg!(target_arch = "aarch64") {
+            Some(Arch::Arm64)
+        } else if cfg!(target_arch = "arm") {
+            Some(Arch::Arm)
+        } else if cfg!(target_arch = "x86_64") {
+            Some(Arch::X86_64)
+        } else if cfg!(target_arch = "x86") {
+            Some(Arch::X86)
+        } else {
+            None
+        }
+    }
+
+    pub const fn from_elf_machine(machine: u16) -> Option<Arch> {
+        match machine {
+            elf::EM_AARCH64 => Some(Arch::Arm64),
+            elf::EM_ARM => Some(Arch::Arm),
+            elf::EM_X86_64 => Some(Arch::X86_64),
+            elf::EM_386 => Some(Arch::X86),
+            _ => None,
+        }
+    }
+}
+
+static TARGET_ARCH: Mutex<Option<Arch>> = Mutex::new(None);
+
+// overrides the arch of the candidates, used to resolve signatures of another arch offline
+pub fn set_target_arch(arch: Arch) {
+    TARGET_ARCH.lock().unwrap().replace(arch);
+}
+
+pub fn target_arch() -> Option<Arch> {
+    TARGET_ARCH.lock().unwrap().or(Arch::current())
+}
+
+// dotted numeric version such as "12.84.0.38"
+#[derive(Debug, Clone, Eq)]
+pub struct ClientVersion(Vec<u32>);
+
+impl FromStr for ClientVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim().split('.').map(|part| {
+            part.parse::<u32>().map_err(|_| format!("invalid version component {:?} in {:?}", part, s))
+        }).collect::<Result<Vec<_>, _>>().map(ClientVersion)
+    }
+}
+
+impl Ord for ClientVersion {
+    // missing components compare as zero so "12.84" == "12.84.0"
+    fn cmp(&self, other: &Self) -> Ordering {
+        let length = self.0.len().max(other.0.len());
+        (0..length).map(|i| {
+            self.0.get(i).copied().unwrap_or(0).cmp(&other.0.get(i).copied().unwrap_or(0))
+        }).find(|ordering| ordering.is_ne()).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialEq for ClientVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl PartialOrd for ClientVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for ClientVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.iter().map(|part| part.to_string()).collect::<Vec<_>>().join("."))
+    }
+}
+
+static CLIENT_VERSION: Mutex<Option<ClientVersion>> = Mutex::new(None);
+
+pub fn set_client_version(version: &str) {
+    match version.parse::<ClientVersion>() {
+        Ok(version) => {
+            debug!("Client version: {}", version);
+            CLIENT_VERSION.lock().unwrap().replace(version);
+        }
+        Err(error) => warn!("Unable to parse client version: {}", error),
+    }
+}
+
+pub fn client_version() -> Option<ClientVersion> {
+    CLIENT_VERSION.lock().unwrap().clone()
+}
+
+// picks a single match when a candidate pattern matches more than once
+#[derive(Debug, Clone, Copy)]
+pub enum Disambiguation {
+    // index of the match, in address order
+    Nth(usize),
+    // the match followed or preceded by the secondary pattern within the given distance in bytes
+    Near { pattern: &'static str, distance: usize },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateKind {
+    // byte signature matched in the executable regions
+    #[default]
+    Bytes,
+    // string literal, resolves to the functions referencing it
+    StringRef,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate {
+    pub arch: Arch,
+    pub kind: CandidateKind,
+    // byte signature or string literal depending on the kind
+    pub pattern: &'static str,
+    pub offset: i64,
+    // inclusive client version range the candidate applies to
+    pub min_version: Option<&'static str>,
+    pub max_version: Option<&'static str>,
+    pub disambiguation: Option<Disambiguation>,
+    // walk back from the offset match to the enclosing function prologue
+    pub function_start: bool,
+}
+
+impl Candidate {
+    pub const fn arm64(pattern: &'static str, offset: i64) -> Self {
+        Self::new(Arch::Arm64, pattern, offset)
+    }
+
+    pub const fn arm(pattern: &'static str, offset: i64) -> Self {
+        Self::new(Arch::Arm, pattern, offset)
+    }
+
+    pub const fn x86_64(pattern: &'static str, offset: i64) -> Self {
+        Self::new(Arch::X86_64, pattern, offset)
+    }
+
+    pub const fn x86(pattern: &'static str, offset: i64) -> Self {
+        Self::new(Arch::X86, pattern, offset)
+    }
+
+    pub const fn new(arch: Arch, pattern: &'static str, offset: i64) -> Self {
+        Self {
+            arch,
+            kind: CandidateKind::Bytes,
+            pattern,
+            offset,
+            min_version: None,
+            max_version: None,
+            disambiguation: None,
+            function_start: false,
+        }
+    }
+
+    pub const fn string_ref(arch: Arch, literal: &'static str) -> Self {
+        let mut candidate = Self::new(arch, literal, 0);
+        candidate.kind = CandidateKind::StringRef;
+        candidate
+    }
+
+    pub const fn min_version(mut self, version: &'static str) -> Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    pub const fn max_version(mut self, version: &'static str) -> Self {
+        self.max_version = Some(version);
+        self
+    }
+
+    pub const fn nth(mut self, index: usize) -> Self {
+        self.disambiguation = Some(Disambiguation::Nth(index));
+        self
+    }
+
+    pub const fn near(mut self, pattern: &'static str, distance: usize) -> Self {
+        self.disambiguation = Some(Disambiguation::Near { pattern, distance });
+        self
+    }
+
+    pub const fn function_start(mut self) -> Self {
+        self.function_start = true;
+        self
+    }
+
+    // candidates with a version range are still tried when the client version is unknown
+    pub fn supports(&self, arch: Arch, version: Option<&ClientVersion>) -> bool {
+        if self.arch != arch {
+            return false;
+        }
+
+        let Some(version) = version else {
+            return true;
+        };
+
+        let in_range = |bound: Option<&str>, expected: Ordering| {
+            bound.is_none_or(|bound| match bound.parse::<ClientVersion>() {
+                Ok(bound) => version.cmp(&bound) != expected,
+                Err(error) => {
+                    warn!("Invalid candidate version bound: {}", error);
+                    false
+                }
+            })
+        };
+
+        in_range(self.min_version, Ordering::Less) && in_range(self.max_version, Ordering::Greater)
+    }
+}
+
+// ordered list of candidates, the first one resolving to a single match wins
+#[derive(Debug)]
+pub struct SignatureDef {
+    pub name: &'static str,
+    pub candidates: &'static [Candidate],
+}
+
+impl SignatureDef {
+    // candidates loaded at init replace the built-in ones of the same arch
+    pub fn current_candidates(&self) -> Vec<(usize, &Candidate)> {
+        let Some(arch) = target_arch() else {
+            return Vec::new();
+        };
+        let version = client_version();
+
+        let candidates = match overrides::find_override(self.name) {
+            Some(definition) if definition.candidates.iter().any(|candidate| candidate.arch == arch) => definition.candidates,
+            _ => self.candidates,
+        };
+
+        candidates.iter().enumerate().filter(|(_, candidate)| candidate.supports(arch, version.as_ref())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> ClientVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn compares_versions() {
+        assert!(version("12.84.0.38") > version("12.84.0.9"));
+        assert!(version("12.9") < version("12.10"));
+        assert_eq!(version("12.84"), version("12.84.0.0"));
+        assert!("12.x".parse::<ClientVersion>().is_err());
+    }
+
+    #[test]
+    fn filters_candidates_by_version() {
+        let candidate = Candidate::arm64("FF", 0).min_version("12.80").max_version("12.89.9");
+
+        assert!(candidate.supports(Arch::Arm64, Some(&version("12.80.0.12"))));
+        assert!(candidate.supports(Arch::Arm64, Some(&version("12.89.9"))));
+        assert!(!candidate.supports(Arch::Arm64, Some(&version("12.79.1"))));
+        assert!(!candidate.supports(Arch::Arm64, Some(&version("12.90"))));
+        assert!(!candidate.supports(Arch::Arm, Some(&version("12.85"))));
+        assert!(!candidate.supports(Arch::X86_64, Some(&version("12.85"))));
+        assert!(candidate.supports(Arch::Arm64, None));
+    }
+}
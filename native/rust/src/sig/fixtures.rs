@@ -0,0 +1,9 @@
+// machine code built from instruction words for the tests of the signature layer
+
+pub(crate) fn arm64(instructions: &[u32]) -> Vec<u8> {
+    instructions.iter().flat_map(|insn| insn.to_le_bytes()).collect()
+}
+
+pub(crate) fn thumb(halfwords: &[u16]) -> Vec<u8> {
+    halfwords.iter().flat_map(|halfword| halfword.to_le_bytes()).collect()
+}
@@ -0,0 +1,363 @@
+use std::{error::Error, fmt};
+
+use crate::mapped_lib::MappedLib;
+
+use super::{definition::Arch, executable_regions, scanner, signature::Signature};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeneratorError {
+    UnsupportedArch(Arch),
+    NoTarget,
+    AddressNotMapped,
+    NotUnique,
+}
+
+impl fmt::Display for GeneratorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeneratorError::UnsupportedArch(arch) => write!(f, "signatures can't be generated for {:?}", arch),
+            GeneratorError::NoTarget => write!(f, "no target given"),
+            GeneratorError::AddressNotMapped => write!(f, "the address is not in an executable region"),
+            GeneratorError::NotUnique => write!(f, "no unique pattern found within the search limits"),
+        }
+    }
+}
+
+impl Error for GeneratorError {}
+
+// code of a module and the location of the function to generate a signature for
+pub struct Target<'a> {
+    pub code: Vec<&'a [u8]>,
+    pub region: usize,
+    pub offset: usize,
+}
+
+impl<'a> Target<'a> {
+    // the thumb bit of the address is ignored
+    pub fn from_mapped_lib(mapped_lib: &'a MappedLib, address: usize) -> Result<Self, GeneratorError> {
+        let regions = executable_regions(mapped_lib);
+        let address = address & !1;
+        let region = regions.iter().position(|region| (region.start as usize..region.end as usize).contains(&address)).ok_or(GeneratorError::AddressNotMapped)?;
+
+        Ok(Self {
+            offset: address - regions[region].start as usize,
+            region,
+            code: regions.into_iter().map(|region| region.bytes()).collect(),
+        })
+    }
+
+    fn bytes(&self, length: usize) -> &[u8] {
+        let code = self.code[self.region];
+        &code[self.offset..(self.offset + length).min(code.len())]
+    }
+
+    fn count_matches(&self, signature: &Signature) -> usize {
+        self.code.iter().map(|code| scanner::scan(code, signature, false).len()).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratorOptions {
+    // longest pattern tried, in bytes
+    pub max_length: usize,
+    // furthest distance from the function start the pattern can begin at, in bytes
+    pub max_start: usize,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        Self {
+            max_length: 0x100,
+            max_start: 0x40,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedSignature {
+    pub arch: Arch,
+    pub signature: Signature,
+    // offset from the match to the function, as used by the candidates
+    pub offset: i64,
+}
+
+impl GeneratedSignature {
+    // candidate definition ready to paste in a SignatureDef
+    pub fn to_candidate(&self) -> String {
+        let constructor = match self.arch {
+            Arch::Arm64 => "arm64",
+            Arch::Arm => "arm",
+            Arch::X86_64 => "x86_64",
+            Arch::X86 => "x86",
+        };
+        let offset = match self.offset {
+            0 => "0".to_string(),
+            offset if offset < 0 => format!("-0x{:X}", offset.unsigned_abs()),
+            offset => format!("0x{:X}", offset),
+        };
+
+        format!("Candidate::{}(\"{}\", {}),", constructor, self.signature, offset)
+    }
+}
+
+// converts a mask of the fixed instruction bits to nibble masks, nibbles with a variable bit become wildcards
+fn nibble_mask(fixed_bits: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    fixed_bits.iter().map(|bits| {
+        (if bits & 0xF0 == 0xF0 { 0xF0 } else { 0 }) | (if bits & 0x0F == 0x0F { 0x0F } else { 0 })
+    })
+}
+
+// fixed bits of an arm64 instruction, pc relative immediates and the page offsets completing an ADRP are variable
+fn arm64_fixed_bits(insn: u32, page_registers: &mut u32) -> u32 {
+    let rn = 1 << ((insn >> 5) & 0x1F);
+
+    match insn {
+        // B, BL
+        _ if insn & 0x7C000000 == 0x14000000 => 0xFC000000,
+        // B.cond, CBZ, CBNZ, LDR (literal)
+        _ if insn & 0xFF000010 == 0x54000000 || insn & 0x7E000000 == 0x34000000 || insn & 0x3B000000 == 0x18000000 => 0xFF00001F,
+        // TBZ, TBNZ
+        _ if insn & 0x7E000000 == 0x36000000 => 0xFFF8001F,
+        // ADR, ADRP
+        _ if insn & 0x1F000000 == 0x10000000 => {
+            *page_registers |= 1 << (insn & 0x1F);
+            0x9F00001F
+        }
+        // ADD (immediate), LDR/STR (unsigned offset) based on an ADRP register
+        _ if (insn & 0x7F800000 == 0x11000000 || insn & 0x3B000000 == 0x39000000) && *page_registers & rn != 0 => 0xFFC003FF,
+        _ => 0xFFFFFFFF,
+    }
+}
+
+fn arm64_mask(code: &[u8]) -> Vec<u8> {
+    let mut page_registers = 0;
+
+    code.chunks(4).flat_map(|bytes| {
+        let fixed_bits = match bytes.try_into() {
+            Ok(bytes) => arm64_fixed_bits(u32::from_le_bytes(bytes), &mut page_registers),
+            Err(_) => 0xFFFFFFFF,
+        };
+        nibble_mask(&fixed_bits.to_le_bytes()[..bytes.len()]).collect::<Vec<_>>()
+    }).collect()
+}
+
+// fixed bits of a thumb instruction given as one or two halfwords
+fn thumb_fixed_bits(first: u16, second: Option<u16>) -> (u16, u16) {
+    match (first, second) {
+        // B.W, BL, BLX
+        (_, Some(second)) if first & 0xF800 == 0xF000 && second & 0x8000 == 0x8000 => (0xF800, 0xD000),
+        // LDR.W (literal)
+        (_, Some(_)) if first & 0xFF7F == 0xF85F => (0xFF7F, 0xF000),
+        (_, Some(_)) => (0xFFFF, 0xFFFF),
+        // LDR (literal), ADR, B.cond
+        _ if first & 0xF800 == 0x4800 || first & 0xF800 == 0xA000 || (first & 0xF000 == 0xD000 && first & 0x0E00 != 0x0E00) => (0xFF00, 0),
+        // B
+        _ if first & 0xF800 == 0xE000 => (0xF800, 0),
+        // CBZ, CBNZ
+        _ if first & 0xF500 == 0xB100 => (0xFD07, 0),
+        _ => (0xFFFF, 0),
+    }
+}
+
+fn thumb_mask(code: &[u8]) -> Vec<u8> {
+    let halfwords = code.chunks(2).map(|bytes| bytes.try_into().map(u16::from_le_bytes).ok()).collect::<Vec<_>>();
+    let mut mask = Vec::with_capacity(code.len());
+    let mut index = 0;
+
+    while index < halfwords.len() {
+        let Some(first) = halfwords[index] else {
+            mask.push(0xFF);
+            break;
+        };
+
+        let wide = matches!(first >> 11, 0b11101..=0b11111);
+        let second = halfwords.get(index + 1).copied().flatten().filter(|_| wide);
+        let (first_bits, second_bits) = thumb_fixed_bits(first, second);
+
+        mask.extend(nibble_mask(&first_bits.to_le_bytes()));
+        if second.is_some() {
+            mask.extend(nibble_mask(&second_bits.to_le_bytes()));
+            index += 1;
+        }
+        index += 1;
+    }
+
+    mask.truncate(code.len());
+    mask
+}
+
+// nibble masks of the code with the relocation dependent bits wildcarded
+pub fn relocation_mask(arch: Arch, code: &[u8]) -> Result<Vec<u8>, GeneratorError> {
+    match arch {
+        Arch::Arm64 => Ok(arm64_mask(code)),
+        Arch::Arm => Ok(thumb_mask(code)),
+        _ => Err(GeneratorError::UnsupportedArch(arch)),
+    }
+}
+
+fn instruction_size(arch: Arch) -> usize {
+    match arch {
+        Arch::Arm64 => 4,
+        _ => 2,
+    }
+}
+
+// pattern of the window, trailing wildcards are dropped
+fn window_signature(bytes: &[u8], mask: &[u8]) -> Option<Signature> {
+    let length = mask.iter().rposition(|&mask| mask != 0)? + 1;
+    let signature = Signature::new(bytes[..length].to_vec(), mask[..length].to_vec(), None);
+    (!signature.anchor().is_empty()).then_some(signature)
+}
+
+/*
+ * finds the shortest pattern matching the function of every target exactly once
+ * - the pattern starts at the function or at most max_start bytes into it
+ * - relocation dependent bits and nibbles differing between the targets are wildcarded
+ */
+pub fn generate(arch: Arch, targets: &[Target], options: GeneratorOptions) -> Result<GeneratedSignature, GeneratorError> {
+    let reference = targets.first().ok_or(GeneratorError::NoTarget)?;
+    let length = targets.iter().map(|target| target.bytes(options.max_length).len()).min().unwrap_or(0);
+    let bytes = reference.bytes(length).to_vec();
+    let mut mask = relocation_mask(arch, &bytes)?;
+
+    for target in &targets[1..] {
+        let other = target.bytes(length);
+        let other_mask = relocation_mask(arch, other)?;
+
+        for (i, mask) in mask.iter_mut().enumerate() {
+            let differing = bytes[i] ^ other[i];
+            *mask &= other_mask[i] & !(if differing & 0xF0 != 0 { 0xF0 } else { 0 } | if differing & 0x0F != 0 { 0x0F } else { 0 });
+        }
+    }
+
+    let step = instruction_size(arch);
+    let is_unique = |start: usize, end: usize| {
+        window_signature(&bytes[start..end], &mask[start..end]).filter(|signature| {
+            targets.iter().all(|target| target.count_matches(signature) == 1)
+        })
+    };
+
+    let mut best: Option<(usize, Signature)> = None;
+
+    for start in (0..=options.max_start.min(length.saturating_sub(step))).step_by(step) {
+        let longest = (length - start) / step;
+
+        if is_unique(start, start + longest * step).is_none() {
+            continue;
+        }
+
+        // a longer window only matches a subset of the shorter one, so the shortest unique window can be bisected
+        let (mut low, mut high) = (1, longest);
+        while low < high {
+            let middle = (low + high) / 2;
+            if is_unique(start, start + middle * step).is_some() {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+
+        let signature = is_unique(start, start + low * step).expect("window became ambiguous");
+        if best.as_ref().is_none_or(|(_, best)| signature.len() < best.len()) {
+            best = Some((start, signature));
+        }
+    }
+
+    let (start, signature) = best.ok_or(GeneratorError::NotUnique)?;
+
+    Ok(GeneratedSignature {
+        arch,
+        signature,
+        // thumb functions are resolved with the thumb bit set
+        offset: if arch == Arch::Arm { 1 - start as i64 } else { -(start as i64) },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig::fixtures::arm64;
+
+    fn target(code: &[u8], offset: usize) -> Target<'_> {
+        Target { code: vec![code], region: 0, offset }
+    }
+
+    #[test]
+    fn wildcards_arm64_relocations() {
+        let code = arm64(&[
+            0x94000010, // bl #0x40
+            0xD0000000, // adrp x0, #0x2000
+            0x91048C00, // add x0, x0, #0x123
+            0x91048C21, // add x1, x1, #0x123
+            0xB4000080, // cbz x0, #0x10
+        ]);
+        let signature = window_signature(&code, &relocation_mask(Arch::Arm64, &code).unwrap()).unwrap();
+        assert_eq!(signature.to_string(), "?? ?? ?? 9? ?0 ?? ?? ?0 00 ?? ?? 91 21 8C 04 91 ?0 ?? ?? B4");
+    }
+
+    #[test]
+    fn wildcards_thumb_relocations() {
+        let code = [
+            0xF000u16, 0xF800, // bl #0x4
+            0x4802, // ldr r0, [pc, #8]
+            0xB5B0, // push {r4, r5, r7, lr}
+        ].iter().flat_map(|halfword| halfword.to_le_bytes()).collect::<Vec<_>>();
+        let signature = window_signature(&code, &relocation_mask(Arch::Arm, &code).unwrap()).unwrap();
+        assert_eq!(signature.to_string(), "?? F? ?? ?? ?? 48 B0 B5");
+    }
+
+    #[test]
+    fn generates_shortest_unique_pattern() {
+        // the first instruction of the function also appears elsewhere
+        let code = arm64(&[
+            0xA9BF7BFD, // stp x29, x30, [sp, #-0x10]!
+            0xD65F03C0, // ret
+            0xA9BF7BFD, // stp x29, x30, [sp, #-0x10]!
+            0x910003FD, // mov x29, sp
+            0x94000010, // bl #0x40
+            0xD65F03C0, // ret
+        ]);
+
+        let generated = generate(Arch::Arm64, &[target(&code, 8)], GeneratorOptions::default()).unwrap();
+        assert_eq!(generated.signature.to_string(), "FD 03 00 91");
+        assert_eq!(generated.offset, -4);
+        assert_eq!(generated.to_candidate(), "Candidate::arm64(\"FD 03 00 91\", -0x4),");
+
+        let generated = generate(Arch::Arm64, &[target(&code, 8)], GeneratorOptions { max_start: 0, ..Default::default() }).unwrap();
+        assert_eq!(generated.signature.to_string(), "FD 7B BF A9 FD 03 00 91");
+        assert_eq!(generated.offset, 0);
+    }
+
+    #[test]
+    fn generates_pattern_stable_across_builds() {
+        let first = arm64(&[
+            0xA9BF7BFD, // stp x29, x30, [sp, #-0x10]!
+            0xD65F03C0, // ret
+            0xA9BF7BFD, // stp x29, x30, [sp, #-0x10]!
+            0x52800020, // mov w0, #1
+            0x94000010, // bl #0x40
+            0xD65F03C0, // ret
+        ]);
+        let second = arm64(&[
+            0xA9BF7BFD, // stp x29, x30, [sp, #-0x10]!
+            0x52800040, // mov w0, #2
+            0x94000020, // bl #0x80
+            0xD65F03C0, // ret
+            0xA9BF7BFD, // stp x29, x30, [sp, #-0x10]!
+            0xD65F03C0, // ret
+        ]);
+
+        // the immediate of the mov differs between the builds
+        let generated = generate(Arch::Arm64, &[target(&first, 8), target(&second, 0)], GeneratorOptions::default()).unwrap();
+        assert_eq!(generated.signature.to_string(), "?0 00 80 52");
+        assert_eq!(generated.offset, -4);
+        assert_eq!(target(&first, 0).count_matches(&generated.signature), 1);
+        assert_eq!(target(&second, 0).count_matches(&generated.signature), 1);
+    }
+
+    #[test]
+    fn reports_ambiguous_functions() {
+        let code = arm64(&[0xD65F03C0, 0xD65F03C0, 0xD65F03C0]); // ret, ret, ret
+        assert_eq!(generate(Arch::Arm64, &[target(&code, 4)], GeneratorOptions::default()).unwrap_err(), GeneratorError::NotUnique);
+    }
+}
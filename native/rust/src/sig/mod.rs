@@ -0,0 +1,427 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+
+use procfs::process::MMPermissions;
+
+use crate::mapped_lib::{MappedLib, MappedRegion};
+
+pub mod arm64;
+pub mod cache;
+pub mod definition;
+#[cfg(test)]
+mod fixtures;
+pub mod generator;
+pub mod overrides;
+pub mod prologue;
+pub mod report;
+pub mod scanner;
+pub mod signature;
+pub mod xref;
+
+pub use definition::{Arch, Candidate, CandidateKind, Disambiguation, SignatureDef};
+
+use cache::{ModuleIdentity, SignatureCache, CACHE_VERSION};
+use report::{Ambiguity, MatchInfo, SignatureTiming, Unresolved, UnresolvedReason};
+use scanner::ScanTimedOut;
+use signature::{Operand, Signature};
+
+
+static SIGNATURE_CACHE: Mutex<SignatureCache> = Mutex::new(SignatureCache::new());
+static SCAN_DEADLINE: Mutex<Option<Instant>> = Mutex::new(None);
+// signatures restored from the cache supplied at init
+static RESTORED_SIGNATURES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// address of a signature, cached if its candidate was restored from the cache supplied at init
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedSignature {
+    pub address: usize,
+    pub cached: bool,
+}
+
+// every scan started after the budget has elapsed gives up, None removes the limit
+pub fn set_scan_budget(budget: Option<Duration>) {
+    *SCAN_DEADLINE.lock().unwrap() = budget.map(|budget| Instant::now() + budget);
+}
+
+fn scan_deadline() -> Option<Instant> {
+    *SCAN_DEADLINE.lock().unwrap()
+}
+
+fn check_scan_budget() -> Result<(), ScanTimedOut> {
+    if scan_deadline().is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(ScanTimedOut);
+    }
+    Ok(())
+}
+
+// scans every executable region for the signatures at once, returns the absolute addresses of each signature
+fn scan_executable_regions(regions: &[&MappedRegion], signatures: &[Signature]) -> Result<Vec<Vec<usize>>, ScanTimedOut> {
+    let haystacks = regions.iter().map(|region| region.bytes()).collect::<Vec<_>>();
+    let results = scanner::scan_parallel(&haystacks, signatures, scan_deadline()).inspect_err(|_| {
+        warn!("Signature scan budget exhausted");
+        report::record(|report| report.budget_exhausted = true);
+    })?;
+
+    let mut addresses = vec![Vec::new(); signatures.len()];
+    for (region, results) in regions.iter().zip(results) {
+        for (index, offsets) in results.into_iter().enumerate() {
+            addresses[index].extend(offsets.into_iter().map(|offset| region.start as usize + offset));
+        }
+    }
+
+    Ok(addresses)
+}
+
+// identifies the module the cache belongs to, must be called before loading a cache
+pub fn set_cache_module(mapped_lib: &MappedLib) {
+    match ModuleIdentity::from_mapped_lib(mapped_lib) {
+        Ok(identity) => {
+            debug!("Signature cache module: {:?}", identity);
+            SIGNATURE_CACHE.lock().unwrap().module = Some(identity);
+        }
+        Err(error) => warn!("Unable to identify module: {}", error),
+    }
+}
+
+pub fn add_signatures(signature_cache: SignatureCache) {
+    let mut cache = SIGNATURE_CACHE.lock().unwrap();
+
+    if signature_cache.version != CACHE_VERSION {
+        warn!("Dropping signature cache with version {}", signature_cache.version);
+        return;
+    }
+
+    if cache.module.is_none() || signature_cache.module != cache.module {
+        info!("Dropping {} cached signatures computed for another module build", signature_cache.entries.len());
+        return;
+    }
+
+    RESTORED_SIGNATURES.lock().unwrap().extend(signature_cache.entries.iter().map(|entry| entry.signature.clone()));
+    cache.entries.extend(signature_cache.entries);
+}
+
+pub fn get_signatures() -> SignatureCache {
+    SIGNATURE_CACHE.lock().unwrap().clone()
+}
+
+pub fn find_signatures(region: &MappedRegion, signature: &Signature, once: bool) -> Vec<usize> {
+    scanner::scan(region.bytes(), signature, once).into_iter().map(|offset| region.start as usize + offset).collect()
+}
+
+// execute-only regions are read through a copy of their pages
+fn executable_regions(mapped_lib: &MappedLib) -> Vec<&MappedRegion> {
+    mapped_lib.regions.iter().filter(|region| region.perms.contains(MMPermissions::EXECUTE) && region.end > region.start).collect()
+}
+
+// cached offsets and operand targets are virtual addresses of the module, relative to its load bias
+fn module_base(mapped_lib: &MappedLib) -> usize {
+    mapped_lib.load_bias().unwrap_or_else(|| mapped_lib.regions.iter().map(|region| region.start as usize).min().unwrap_or(0))
+}
+
+fn containing_region<'a>(regions: &[&'a MappedRegion], address: usize) -> Option<&'a MappedRegion> {
+    regions.iter().copied().find(|region| (region.start as usize..region.end as usize).contains(&address))
+}
+
+fn decode_operand(regions: &[&MappedRegion], address: usize, offset: i64, decode: fn(&[u8], usize, u64) -> Option<u64>) -> Option<u64> {
+    let address = address.checked_add_signed(offset as isize)?;
+    let region = containing_region(regions, address)?;
+    decode(region.bytes(), address - region.start as usize, region.start)
+}
+
+// the scanner only checks the bytes, the instruction operands are decoded from the memory around each match
+fn operands_match(regions: &[&MappedRegion], module_base: usize, signature: &Signature, address: usize) -> bool {
+    signature.operands().iter().all(|operand| match *operand {
+        Operand::Branch { offset } => decode_operand(regions, address, offset, arm64::branch_target).is_some(),
+        Operand::PageAddress { offset, target } => {
+            decode_operand(regions, address, offset, arm64::page_address).and_then(|value| value.checked_sub(module_base as u64)) == Some(target)
+        }
+    })
+}
+
+// cached offsets are checked against the memory before being trusted
+fn find_cached_signature(regions: &[&MappedRegion], module_base: usize, signature: &Signature) -> Option<Vec<usize>> {
+    let cache_key = signature.to_string();
+    let Some(offsets) = SIGNATURE_CACHE.lock().unwrap().get(&cache_key).cloned() else {
+        report::record(|report| report.cache.misses += 1);
+        return None;
+    };
+
+    let addresses = offsets.iter().map(|offset| module_base + offset).collect::<Vec<_>>();
+    let valid = addresses.iter().all(|&address| {
+        containing_region(regions, address).is_some_and(|region| {
+            signature.matches_at(region.bytes(), address - region.start as usize)
+        }) && operands_match(regions, module_base, signature, address)
+    });
+
+    if valid {
+        report::record(|report| report.cache.hits += 1);
+        return Some(addresses);
+    }
+
+    warn!("Cached signature {} no longer matches, rescanning", cache_key);
+    SIGNATURE_CACHE.lock().unwrap().remove(&cache_key);
+    RESTORED_SIGNATURES.lock().unwrap().retain(|restored| *restored != cache_key);
+    report::record(|report| {
+        report.cache.invalidations += 1;
+        report.cache.misses += 1;
+    });
+    None
+}
+
+// resolves all the named patterns with a single walk over the executable regions, results are stored in the signature cache
+pub fn find_signatures_batch(mapped_lib: &MappedLib, patterns: &[(String, &str)]) -> HashMap<String, Vec<usize>> {
+    let executable_regions = executable_regions(mapped_lib);
+    let mut resolved = HashMap::new();
+
+    if executable_regions.is_empty() {
+        return resolved;
+    }
+
+    let module_base = module_base(mapped_lib);
+    let mut pending = Vec::new();
+
+    for (name, pattern) in patterns {
+        let signature = match pattern.parse::<Signature>() {
+            Ok(signature) => signature,
+            Err(error) => {
+                error!("Invalid signature {} {:?}: {}", name, pattern, error);
+                continue;
+            }
+        };
+
+        if let Some(addresses) = find_cached_signature(&executable_regions, module_base, &signature) {
+            resolved.insert(name.clone(), addresses);
+            continue;
+        }
+        pending.push((name, signature));
+    }
+
+    if pending.is_empty() {
+        return resolved;
+    }
+
+    let signatures = pending.iter().map(|(_, signature)| signature.clone()).collect::<Vec<_>>();
+    let Ok(addresses) = scan_executable_regions(&executable_regions, &signatures) else {
+        // partial results are not cached, the remaining signatures are reported once their hooks look them up
+        return resolved;
+    };
+
+    let mut cache = SIGNATURE_CACHE.lock().unwrap();
+
+    for ((name, signature), mut addresses) in pending.into_iter().zip(addresses) {
+        addresses.retain(|&address| operands_match(&executable_regions, module_base, &signature, address));
+        cache.insert(signature.to_string(), addresses.iter().map(|address| address - module_base).collect());
+
+        if addresses.is_empty() {
+            debug!("Signature {} not found", name);
+        }
+        resolved.insert(name.clone(), addresses);
+    }
+
+    resolved
+}
+
+// scans every byte candidate applying to the current client at once so the hooks only hit the cache
+pub fn prefetch_signatures(mapped_lib: &MappedLib, signatures: &[&SignatureDef]) {
+    let patterns = signatures.iter().flat_map(|signature| {
+        signature.current_candidates().into_iter().filter(|(_, candidate)| candidate.kind == CandidateKind::Bytes).map(|(index, candidate)| (format!("{}#{}", signature.name, index), candidate.pattern))
+    }).collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let resolved = find_signatures_batch(mapped_lib, &patterns);
+    report::record(|report| report.prefetch_us = start.elapsed().as_micros() as u64);
+    debug!("Prefetched {} signature candidates, {} found", patterns.len(), resolved.values().filter(|addresses| !addresses.is_empty()).count());
+}
+
+// returns every match of the pattern in the executable regions, fails once the scan budget is exhausted
+pub fn find_signature_matches(mapped_lib: &MappedLib, pattern: &str) -> Result<Vec<usize>, ScanTimedOut> {
+    let signature = match pattern.parse::<Signature>() {
+        Ok(signature) => signature,
+        Err(error) => {
+            error!("Invalid signature {:?}: {}", pattern, error);
+            return Ok(Vec::new());
+        }
+    };
+
+    let executable_regions = executable_regions(mapped_lib);
+    if executable_regions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let module_base = module_base(mapped_lib);
+
+    if let Some(addresses) = find_cached_signature(&executable_regions, module_base, &signature) {
+        return Ok(addresses);
+    }
+
+    check_scan_budget()?;
+    let mut addresses = scan_executable_regions(&executable_regions, std::slice::from_ref(&signature))?.remove(0);
+    debug!("Found {} results for {}", addresses.len(), signature);
+
+    addresses.retain(|&address| operands_match(&executable_regions, module_base, &signature, address));
+
+    SIGNATURE_CACHE.lock().unwrap().insert(signature.to_string(), addresses.iter().map(|address| address - module_base).collect());
+    Ok(addresses)
+}
+
+fn describe_matches(mapped_lib: &MappedLib, addresses: &[usize]) -> Vec<MatchInfo> {
+    let executable_regions = executable_regions(mapped_lib);
+    let module_base = module_base(mapped_lib);
+
+    addresses.iter().map(|&address| {
+        let region = executable_regions.iter().find(|region| (region.start as usize..region.end as usize).contains(&address));
+
+        MatchInfo {
+            offset: address - module_base,
+            region_start: region.map_or(0, |region| region.start as usize - module_base),
+            region_end: region.map_or(0, |region| region.end as usize - module_base),
+        }
+    }).collect()
+}
+
+fn disambiguate(mapped_lib: &MappedLib, addresses: &[usize], disambiguation: Disambiguation) -> Option<usize> {
+    match disambiguation {
+        Disambiguation::Nth(index) => addresses.get(index).copied(),
+        Disambiguation::Near { pattern, distance } => {
+            let secondary = pattern.parse::<Signature>().map_err(|error| {
+                error!("Invalid secondary signature {:?}: {}", pattern, error);
+            }).ok()?;
+
+            let executable_regions = executable_regions(mapped_lib);
+            let selected = addresses.iter().copied().filter(|&address| {
+                executable_regions.iter().find(|region| (region.start as usize..region.end as usize).contains(&address)).is_some_and(|region| {
+                    let bytes = region.bytes();
+                    let offset = address - region.start as usize;
+                    let window = &bytes[offset.saturating_sub(distance)..(offset + distance + secondary.len()).min(bytes.len())];
+                    !scanner::scan(window, &secondary, true).is_empty()
+                })
+            }).collect::<Vec<_>>();
+
+            match selected.as_slice() {
+                [address] => Some(*address),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn is_restored(candidate: &Candidate) -> bool {
+    candidate.kind == CandidateKind::Bytes && candidate.pattern.parse::<Signature>().is_ok_and(|signature| {
+        RESTORED_SIGNATURES.lock().unwrap().contains(&signature.to_string())
+    })
+}
+
+// follows the BL operand of the signature if it has one
+fn follow_branch(mapped_lib: &MappedLib, candidate: &Candidate, address: usize) -> Option<usize> {
+    if candidate.kind != CandidateKind::Bytes {
+        return Some(address);
+    }
+    let Some(offset) = candidate.pattern.parse::<Signature>().ok().and_then(|signature| signature.branch()) else {
+        return Some(address);
+    };
+    decode_operand(&executable_regions(mapped_lib), address, offset, arm64::branch_target).map(|target| target as usize)
+}
+
+// resolves the entry point of the function containing the address, thumb entry points have the thumb bit set
+pub fn find_function_start(mapped_lib: &MappedLib, address: usize, arch: Arch) -> Option<usize> {
+    let region = executable_regions(mapped_lib).into_iter().find(|region| (region.start as usize..region.end as usize).contains(&address))?;
+    let code = region.bytes();
+    let offset = address - region.start as usize;
+
+    match arch {
+        Arch::Arm64 => prologue::arm64_function_start(code, offset, prologue::MAX_PROLOGUE_DISTANCE),
+        Arch::Arm => prologue::thumb_function_start(code, offset, prologue::MAX_PROLOGUE_DISTANCE).map(|start| start | 1),
+        Arch::X86_64 | Arch::X86 => prologue::x86_function_start(code, offset, prologue::MAX_PROLOGUE_DISTANCE),
+    }.map(|start| region.start as usize + start)
+}
+
+// tries the candidates in order, the first one resolving to a single match wins. ambiguous candidates are only used with a disambiguation rule
+pub fn find_signature(mapped_lib: &MappedLib, signature: &SignatureDef) -> Option<usize> {
+    resolve(mapped_lib, signature).map(|resolved| resolved.address)
+}
+
+// same as find_signature, also telling whether the address comes from the cache supplied at init
+pub fn resolve(mapped_lib: &MappedLib, signature: &SignatureDef) -> Option<ResolvedSignature> {
+    let start = Instant::now();
+    let result = resolve_signature(mapped_lib, signature);
+
+    let reason = match result {
+        Ok(Some(_)) => None,
+        Ok(None) => {
+            warn!("No candidate of {} matched", signature.name);
+            Some(UnresolvedReason::NotFound)
+        }
+        Err(ScanTimedOut) => {
+            warn!("Gave up resolving {}, the scan budget is exhausted", signature.name);
+            Some(UnresolvedReason::TimedOut)
+        }
+    };
+
+    report::record(|report| {
+        report.timings.push(SignatureTiming {
+            signature: signature.name.to_string(),
+            elapsed_us: start.elapsed().as_micros() as u64,
+            resolved: reason.is_none(),
+        });
+        if let Some(reason) = reason {
+            report.unresolved.push(Unresolved { signature: signature.name.to_string(), reason });
+        }
+    });
+
+    result.ok().flatten()
+}
+
+fn resolve_signature(mapped_lib: &MappedLib, signature: &SignatureDef) -> Result<Option<ResolvedSignature>, ScanTimedOut> {
+    for (index, candidate) in signature.current_candidates() {
+        let addresses = match candidate.kind {
+            CandidateKind::Bytes => find_signature_matches(mapped_lib, candidate.pattern)?,
+            CandidateKind::StringRef => {
+                check_scan_budget()?;
+                xref::find_string_references(mapped_lib, candidate.pattern, candidate.arch)
+            }
+        };
+
+        let address = match (addresses.as_slice(), candidate.disambiguation) {
+            ([], _) => {
+                debug!("{} candidate #{} ({}) not found", signature.name, index, candidate.pattern);
+                continue;
+            }
+            ([address], _) => *address,
+            (_, disambiguation) => {
+                if let Some(address) = disambiguation.and_then(|disambiguation| disambiguate(mapped_lib, &addresses, disambiguation)) {
+                    debug!("{} candidate #{} disambiguated {} matches with {:?}", signature.name, index, addresses.len(), disambiguation);
+                    address
+                } else {
+                    let matches = describe_matches(mapped_lib, &addresses);
+                    warn!("{} candidate #{} ({}) is ambiguous, matches at {:x?}", signature.name, index, candidate.pattern, matches.iter().map(|info| info.offset).collect::<Vec<_>>());
+
+                    report::record(|report| report.ambiguities.push(Ambiguity {
+                        signature: signature.name.to_string(),
+                        candidate: index,
+                        pattern: candidate.pattern.to_string(),
+                        matches,
+                    }));
+                    continue;
+                }
+            }
+        };
+
+        let Some(address) = follow_branch(mapped_lib, candidate, address) else {
+            warn!("{} candidate #{} has no branch to follow at {:#x}", signature.name, index, address);
+            continue;
+        };
+        let mut address = (address as i64 + candidate.offset) as usize;
+
+        if candidate.function_start {
+            let Some(function_start) = find_function_start(mapped_lib, address, candidate.arch) else {
+                warn!("{} candidate #{} has no function prologue before {:#x}", signature.name, index, address);
+                continue;
+            };
+            address = function_start;
+        }
+
+        info!("{} resolved with candidate #{} ({}) at {:#x}", signature.name, index, candidate.pattern, address);
+        return Ok(Some(ResolvedSignature { address, cached: is_restored(candidate) }));
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,241 @@
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use super::{definition::ClientVersion, report, signature::Signature, Arch, Candidate, CandidateKind, Disambiguation, SignatureDef};
+
+/*
+ * signature definitions supplied at init, replacing the built-in candidates of the named signatures
+ * {
+ *   "signatures": {
+ *     "sqlite3_open": [
+ *       { "arch": "arm64", "pattern": "FF 83 01 D1 ?? ?? 01 A9", "offset": 0, "nth": 1 },
+ *       { "arch": "arm", "kind": "string_ref", "pattern": "RTRIM" }
+ *     ]
+ *   }
+ * }
+ * - the document is rejected as a whole if any entry is invalid
+ * - built-in candidates stay in use for the archs a signature has no override for
+ */
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct DefinitionDocument {
+    signatures: BTreeMap<String, Vec<CandidateDefinition>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct NearDefinition {
+    pattern: String,
+    distance: usize,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CandidateDefinition {
+    arch: Arch,
+    #[serde(default)]
+    kind: CandidateKind,
+    pattern: String,
+    #[serde(default)]
+    offset: i64,
+    min_version: Option<String>,
+    max_version: Option<String>,
+    nth: Option<usize>,
+    near: Option<NearDefinition>,
+    #[serde(default)]
+    function_start: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DefinitionError {
+    // none when the document itself can't be parsed
+    pub signature: Option<String>,
+    pub candidate: Option<usize>,
+    pub message: String,
+}
+
+impl DefinitionError {
+    fn new(signature: Option<&str>, candidate: Option<usize>, message: impl Into<String>) -> Self {
+        Self { signature: signature.map(str::to_string), candidate, message: message.into() }
+    }
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.signature, self.candidate) {
+            (Some(signature), Some(candidate)) => write!(f, "{} candidate #{}: {}", signature, candidate, self.message),
+            (Some(signature), None) => write!(f, "{}: {}", signature, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+static OVERRIDES: Mutex<Vec<&'static SignatureDef>> = Mutex::new(Vec::new());
+
+// definitions are loaded once per process, leaking them gives the candidates the same lifetime as the built-in ones
+fn leak(value: String) -> &'static str {
+    Box::leak(value.into_boxed_str())
+}
+
+fn validate_version(version: &Option<String>, field: &str) -> Result<Option<ClientVersion>, String> {
+    version.as_ref().map(|version| version.parse::<ClientVersion>().map_err(|error| format!("{}: {}", field, error))).transpose()
+}
+
+fn validate_candidate(definition: &CandidateDefinition) -> Result<(), String> {
+    match definition.kind {
+        CandidateKind::Bytes => {
+            definition.pattern.parse::<Signature>().map_err(|error| format!("pattern: {}", error))?;
+        }
+        CandidateKind::StringRef => {
+            if definition.pattern.is_empty() || definition.pattern.contains('\0') {
+                return Err("pattern: string literals must be non empty and can't contain NUL".to_string());
+            }
+        }
+    }
+
+    let min_version = validate_version(&definition.min_version, "min_version")?;
+    let max_version = validate_version(&definition.max_version, "max_version")?;
+
+    if let (Some(min_version), Some(max_version)) = (&min_version, &max_version) {
+        if min_version > max_version {
+            return Err(format!("min_version {} is greater than max_version {}", min_version, max_version));
+        }
+    }
+
+    if definition.nth.is_some() && definition.near.is_some() {
+        return Err("nth and near are mutually exclusive".to_string());
+    }
+
+    if let Some(near) = &definition.near {
+        near.pattern.parse::<Signature>().map_err(|error| format!("near.pattern: {}", error))?;
+    }
+
+    Ok(())
+}
+
+fn to_candidate(definition: CandidateDefinition) -> Candidate {
+    let mut candidate = Candidate::new(definition.arch, leak(definition.pattern), definition.offset);
+
+    candidate.kind = definition.kind;
+    candidate.min_version = definition.min_version.map(leak);
+    candidate.max_version = definition.max_version.map(leak);
+    candidate.function_start = definition.function_start;
+    candidate.disambiguation = match (definition.nth, definition.near) {
+        (Some(index), _) => Some(Disambiguation::Nth(index)),
+        (_, Some(near)) => Some(Disambiguation::Near { pattern: leak(near.pattern), distance: near.distance }),
+        _ => None,
+    };
+    candidate
+}
+
+// parses and validates a definition document, signatures must be one of the known names
+pub fn parse_definitions(document: &str, known_signatures: &[&str]) -> Result<Vec<SignatureDef>, Vec<DefinitionError>> {
+    let document = serde_json::from_str::<DefinitionDocument>(document).map_err(|error| {
+        vec![DefinitionError::new(None, None, format!("invalid document: {}", error))]
+    })?;
+
+    let mut errors = Vec::new();
+
+    for (name, candidates) in &document.signatures {
+        if !known_signatures.contains(&name.as_str()) {
+            errors.push(DefinitionError::new(Some(name), None, "unknown signature"));
+            continue;
+        }
+
+        if candidates.is_empty() {
+            errors.push(DefinitionError::new(Some(name), None, "no candidate"));
+        }
+
+        for (index, candidate) in candidates.iter().enumerate() {
+            if let Err(message) = validate_candidate(candidate) {
+                errors.push(DefinitionError::new(Some(name), Some(index), message));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(document.signatures.into_iter().map(|(name, candidates)| SignatureDef {
+        name: leak(name),
+        candidates: Box::leak(candidates.into_iter().map(to_candidate).collect::<Vec<_>>().into_boxed_slice()),
+    }).collect())
+}
+
+// replaces the current overrides, returns the number of overridden signatures. errors are added to the signature report
+pub fn load_definitions(document: &str, known_signatures: &[&str]) -> Result<usize, Vec<DefinitionError>> {
+    let definitions = parse_definitions(document, known_signatures).inspect_err(|errors| {
+        errors.iter().for_each(|error| error!("Invalid signature definition: {}", error));
+        report::record(|report| report.definition_errors = errors.clone());
+    })?;
+    let mut overrides = OVERRIDES.lock().unwrap();
+
+    overrides.clear();
+    overrides.extend(definitions.into_iter().map(|definition| &*Box::leak(Box::new(definition))));
+    Ok(overrides.len())
+}
+
+pub fn find_override(name: &str) -> Option<&'static SignatureDef> {
+    OVERRIDES.lock().unwrap().iter().find(|definition| definition.name == name).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: &[&str] = &["sqlite3_open", "unary_call"];
+
+    #[test]
+    fn parses_definitions() {
+        let definitions = parse_definitions(r#"{
+            "signatures": {
+                "sqlite3_open": [
+                    { "arch": "arm64", "pattern": "FF 83 01 D1 ?? ?? 01 A9", "offset": -4, "nth": 1, "min_version": "12.80" },
+                    { "arch": "x86_64", "kind": "string_ref", "pattern": "RTRIM", "function_start": true,
+                      "near": { "pattern": "C3", "distance": 64 } }
+                ]
+            }
+        }"#, KNOWN).unwrap();
+
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].name, "sqlite3_open");
+
+        let [bytes, string_ref] = definitions[0].candidates else {
+            panic!("expected two candidates");
+        };
+        assert_eq!((bytes.arch, bytes.kind, bytes.pattern, bytes.offset), (Arch::Arm64, CandidateKind::Bytes, "FF 83 01 D1 ?? ?? 01 A9", -4));
+        assert!(matches!(bytes.disambiguation, Some(Disambiguation::Nth(1))));
+        assert_eq!(bytes.min_version, Some("12.80"));
+        assert_eq!((string_ref.arch, string_ref.kind, string_ref.function_start), (Arch::X86_64, CandidateKind::StringRef, true));
+        assert!(matches!(string_ref.disambiguation, Some(Disambiguation::Near { pattern: "C3", distance: 64 })));
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        let errors = parse_definitions(r#"{
+            "signatures": {
+                "sqlite3_opne": [{ "arch": "arm64", "pattern": "FF" }],
+                "unary_call": [
+                    { "arch": "arm64", "pattern": "FF ZZ" },
+                    { "arch": "arm", "pattern": "FF", "min_version": "12.90", "max_version": "12.80" },
+                    { "arch": "arm", "pattern": "FF", "nth": 0, "near": { "pattern": "00", "distance": 4 } },
+                    { "arch": "arm", "pattern": "FF" }
+                ]
+            }
+        }"#, KNOWN).unwrap_err();
+
+        assert_eq!(errors.iter().map(|error| (error.signature.as_deref(), error.candidate)).collect::<Vec<_>>(), vec![
+            (Some("sqlite3_opne"), None),
+            (Some("unary_call"), Some(0)),
+            (Some("unary_call"), Some(1)),
+            (Some("unary_call"), Some(2)),
+        ]);
+
+        let errors = parse_definitions(r#"{ "signatures": { "unary_call": [{ "arch": "mips", "pattern": "FF" }] } }"#, KNOWN).unwrap_err();
+        assert_eq!((errors[0].signature.as_ref(), errors.len()), (None, 1));
+        assert!(parse_definitions(r#"{ "signatures": {}, "extra": true }"#, KNOWN).is_err());
+        assert!(parse_definitions(r#"{ "signatures": { "unary_call": [] } }"#, KNOWN).is_err());
+    }
+}
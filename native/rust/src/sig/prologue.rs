@@ -0,0 +1,336 @@
+// walks back from an address inside a function to its entry point by recognising the usual prologue instructions
+
+use crate::memory::{read_u16, read_u32};
+
+pub const MAX_PROLOGUE_DISTANCE: usize = 0x1000;
+
+const ARM64_PACIASP: u32 = 0xD503233F;
+const ARM64_PACIBSP: u32 = 0xD503237F;
+const ARM64_SP: u32 = 31;
+
+fn arm64_is_bti(insn: u32) -> bool {
+    insn & 0xFFFFFF3F == 0xD503241F
+}
+
+// STP (x or d registers) with a signed offset or pre-index, based on SP
+fn arm64_is_stp_sp(insn: u32) -> bool {
+    matches!(insn & 0xFFC00000, 0xA9000000 | 0xA9800000 | 0x6D000000 | 0x6D800000) && (insn >> 5) & 0x1F == ARM64_SP
+}
+
+fn arm64_is_frame_record_store(insn: u32) -> bool {
+    arm64_is_stp_sp(insn) && insn & 0x1F == 29 && (insn >> 10) & 0x1F == 30
+}
+
+// STR Xt, [SP, #imm]!
+fn arm64_is_str_sp_pre_index(insn: u32) -> bool {
+    insn & 0xFFE00C00 == 0xF8000C00 && (insn >> 5) & 0x1F == ARM64_SP
+}
+
+// SUB SP, SP, #imm
+fn arm64_is_sub_sp(insn: u32) -> bool {
+    insn & 0xFF800000 == 0xD1000000 && insn & 0x1F == ARM64_SP && (insn >> 5) & 0x1F == ARM64_SP
+}
+
+fn arm64_allocates_stack(insn: u32) -> bool {
+    arm64_is_sub_sp(insn) || arm64_is_str_sp_pre_index(insn) || (arm64_is_stp_sp(insn) && insn & 0x01800000 == 0x01800000)
+}
+
+fn arm64_is_prologue(insn: u32) -> bool {
+    insn == ARM64_PACIASP || insn == ARM64_PACIBSP || arm64_is_bti(insn) || arm64_is_sub_sp(insn) || arm64_is_stp_sp(insn) || arm64_is_str_sp_pre_index(insn)
+}
+
+// RET, RETAA and RETAB end the previous function. B and BR also jump within a function body, they don't
+fn arm64_is_function_end(insn: u32) -> bool {
+    insn & 0xFFFFFC1F == 0xD65F0000 || insn == 0xD65F0BFF || insn == 0xD65F0FFF
+}
+
+// finds the first instruction of the prologue enclosing the given offset
+pub fn arm64_function_start(code: &[u8], offset: usize, max_distance: usize) -> Option<usize> {
+    let offset = offset & !3;
+    let lowest = offset.saturating_sub(max_distance);
+
+    let anchor = (lowest..=offset).rev().step_by(4).find_map(|position| {
+        let insn = read_u32(code, position)?;
+
+        if position < offset && arm64_is_function_end(insn) {
+            return Some(None);
+        }
+        (insn == ARM64_PACIASP || insn == ARM64_PACIBSP || arm64_is_frame_record_store(insn) || arm64_allocates_stack(insn)).then_some(Some(position))
+    })??;
+
+    // the prologue may save registers or allocate the frame before storing the frame record
+    let mut start = anchor;
+    while start >= 4 && read_u32(code, start - 4).is_some_and(arm64_is_prologue) {
+        start -= 4;
+    }
+
+    Some(start)
+}
+
+fn thumb_is_wide_prefix(halfword: u16) -> bool {
+    matches!(halfword >> 11, 0b11101..=0b11111)
+}
+
+// halfwords looking like wide prefixes alternate between first and second halfwords after the last narrow one
+fn thumb_is_second_halfword(code: &[u8], position: usize) -> bool {
+    let prefixes = (0..position / 2).map(|index| position - (index + 1) * 2).take_while(|&previous| {
+        read_u16(code, previous).is_some_and(thumb_is_wide_prefix)
+    }).count();
+    prefixes % 2 == 1
+}
+
+// PUSH {..., LR} and PUSH.W {..., LR}
+fn thumb_is_push_lr(code: &[u8], position: usize) -> bool {
+    let Some(halfword) = read_u16(code, position) else {
+        return false;
+    };
+
+    if halfword & 0xFF00 == 0xB500 {
+        return true;
+    }
+
+    halfword == 0xE92D && read_u16(code, position + 2).is_some_and(|registers| registers & 0xE000 == 0x4000)
+}
+
+// BX LR, POP {..., PC}, POP.W {..., PC} and LDR.W PC, [SP], #4 end the previous function, B and B.W don't
+fn thumb_is_function_end(code: &[u8], position: usize) -> bool {
+    let Some(halfword) = read_u16(code, position) else {
+        return false;
+    };
+    let second = read_u16(code, position + 2).unwrap_or(0);
+
+    halfword == 0x4770
+        || halfword & 0xFF00 == 0xBD00
+        || (halfword == 0xE8BD && second & 0x8000 != 0)
+        || (halfword == 0xF85D && second == 0xFB04)
+}
+
+// finds the PUSH {..., LR} starting the thumb function enclosing the given offset, the thumb bit is not set
+pub fn thumb_function_start(code: &[u8], offset: usize, max_distance: usize) -> Option<usize> {
+    let offset = offset & !1;
+    let lowest = offset.saturating_sub(max_distance);
+
+    (lowest..=offset).rev().step_by(2).find_map(|position| {
+        // skip the second halfword of a 32-bit instruction
+        if thumb_is_second_halfword(code, position) {
+            return None;
+        }
+
+        if position < offset && thumb_is_function_end(code, position) {
+            return Some(None);
+        }
+        thumb_is_push_lr(code, position).then_some(Some(position))
+    })?
+}
+
+// compilers align x86 functions to 16 bytes and pad the gap after the previous one with int3 or nops
+const X86_FUNCTION_ALIGNMENT: usize = 16;
+const X86_PADDING: &[&[u8]] = &[
+    &[0xCC],
+    &[0x90],
+    &[0x66, 0x90],
+    &[0x0F, 0x1F, 0x00],
+    &[0x0F, 0x1F, 0x40, 0x00],
+    &[0x0F, 0x1F, 0x44, 0x00, 0x00],
+    &[0x0F, 0x1F, 0x80, 0x00, 0x00, 0x00, 0x00],
+    &[0x0F, 0x1F, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+];
+const X86_RET: u8 = 0xC3;
+
+// ENDBR64, ENDBR32, PUSH of a callee-saved register and SUB RSP, imm
+fn x86_is_prologue(code: &[u8]) -> bool {
+    code.starts_with(&[0xF3, 0x0F, 0x1E, 0xFA])
+        || code.starts_with(&[0xF3, 0x0F, 0x1E, 0xFB])
+        || code.first().is_some_and(|&opcode| matches!(opcode, 0x53 | 0x55 | 0x56 | 0x57))
+        || (code.first() == Some(&0x41) && code.get(1).is_some_and(|&opcode| matches!(opcode, 0x54..=0x57)))
+        || (code.first() == Some(&0x48) && code.get(1).is_some_and(|&opcode| matches!(opcode, 0x81 | 0x83)) && code.get(2) == Some(&0xEC))
+}
+
+// finds the aligned prologue following the padding or the return of the previous function, for x86_64 and x86
+pub fn x86_function_start(code: &[u8], offset: usize, max_distance: usize) -> Option<usize> {
+    let offset = offset.min(code.len());
+    let lowest = offset.saturating_sub(max_distance);
+
+    (lowest..=offset - offset % X86_FUNCTION_ALIGNMENT).rev().step_by(X86_FUNCTION_ALIGNMENT).find(|&position| {
+        let previous = &code[..position];
+        let after_previous_function = position == 0 || previous.ends_with(&[X86_RET]) || X86_PADDING.iter().any(|padding| previous.ends_with(padding));
+        after_previous_function && code.get(position..).is_some_and(x86_is_prologue)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig::fixtures::{arm64, thumb};
+
+    #[test]
+    fn arm64_pac_and_frame_allocation() {
+        let code = arm64(&[
+            0xD65F03C0, // ret
+            0xD503233F, // paciasp
+            0xD10183FF, // sub sp, sp, #0x60
+            0xA9057BFD, // stp x29, x30, [sp, #0x50]
+            0x910143FD, // add x29, sp, #0x50
+            0xAA0103E0, // mov x0, x1
+            0x94000010, // bl #0x40
+            0xF9400808, // ldr x8, [x0, #0x10]
+        ]);
+        assert_eq!(arm64_function_start(&code, 7 * 4, MAX_PROLOGUE_DISTANCE), Some(4));
+    }
+
+    #[test]
+    fn arm64_pre_indexed_frame_record() {
+        let code = arm64(&[
+            0x17FFFFF0, // b #-0x40
+            0xA9BE7BFD, // stp x29, x30, [sp, #-0x20]!
+            0xF9000BF3, // str x19, [sp, #0x10]
+            0x910003FD, // mov x29, sp
+            0xAA0003F3, // mov x19, x0
+            0x52800020, // mov w0, #1
+        ]);
+        assert_eq!(arm64_function_start(&code, 5 * 4, MAX_PROLOGUE_DISTANCE), Some(4));
+        // unaligned offsets are rounded down to the instruction
+        assert_eq!(arm64_function_start(&code, 5 * 4 + 2, MAX_PROLOGUE_DISTANCE), Some(4));
+    }
+
+    #[test]
+    fn arm64_simd_saves_and_bti() {
+        let code = arm64(&[
+            0xD65F03C0, // ret
+            0xD503245F, // bti c
+            0x6DBD23E9, // stp d9, d8, [sp, #-0x30]!
+            0xA9017BFD, // stp x29, x30, [sp, #0x10]
+            0x910043FD, // add x29, sp, #0x10
+            0x1E204100, // fmov d0, d8
+        ]);
+        assert_eq!(arm64_function_start(&code, 5 * 4, MAX_PROLOGUE_DISTANCE), Some(4));
+    }
+
+    #[test]
+    fn arm64_leaf_function_without_prologue() {
+        let code = arm64(&[
+            0xD65F03C0, // ret
+            0x8B010000, // add x0, x0, x1
+            0xD65F03C0, // ret
+        ]);
+        assert_eq!(arm64_function_start(&code, 8, MAX_PROLOGUE_DISTANCE), None);
+    }
+
+    #[test]
+    fn arm64_stops_at_the_previous_function() {
+        let code = arm64(&[
+            0xA9BF7BFD, // stp x29, x30, [sp, #-0x10]!
+            0x910003FD, // mov x29, sp
+            0x94000010, // bl #0x40
+            0xA8C17BFD, // ldp x29, x30, [sp], #0x10
+            0xD65F03C0, // ret
+            0x8B010000, // add x0, x0, x1
+            0xD65F03C0, // ret
+        ]);
+        assert_eq!(arm64_function_start(&code, 5 * 4, MAX_PROLOGUE_DISTANCE), None);
+        assert_eq!(arm64_function_start(&code, 3 * 4, MAX_PROLOGUE_DISTANCE), Some(0));
+    }
+
+    #[test]
+    fn arm64_walks_past_branches_in_the_body() {
+        let code = arm64(&[
+            0xD65F03C0, // ret
+            0xA9BE7BFD, // stp x29, x30, [sp, #-0x20]!
+            0x910003FD, // mov x29, sp
+            0x34000060, // cbz w0, #0xc
+            0x52800020, // mov w0, #1
+            0x14000002, // b #8, joins the if and the else
+            0x52800040, // mov w0, #2
+            0xD61F0100, // br x8, a jump table
+            0x17FFFFFE, // b #-8, a loop back-edge
+            0x94000010, // bl #0x40
+        ]);
+        assert_eq!(arm64_function_start(&code, 9 * 4, MAX_PROLOGUE_DISTANCE), Some(4));
+        assert_eq!(arm64_function_start(&code, 6 * 4, MAX_PROLOGUE_DISTANCE), Some(4));
+    }
+
+    #[test]
+    fn arm64_respects_max_distance() {
+        let mut instructions = vec![0xA9BF7BFD]; // stp x29, x30, [sp, #-0x10]!
+        instructions.extend(std::iter::repeat_n(0xD503201F, 16)); // nop
+        let code = arm64(&instructions);
+        assert_eq!(arm64_function_start(&code, 16 * 4, 8 * 4), None);
+        assert_eq!(arm64_function_start(&code, 16 * 4, 16 * 4), Some(0));
+    }
+
+    #[test]
+    fn thumb_narrow_push() {
+        let code = thumb(&[
+            0x4770, // bx lr
+            0xB5B0, // push {r4, r5, r7, lr}
+            0xAF02, // add r7, sp, #8
+            0xE92D, 0x0300, // push.w {r8, r9}
+            0xB084, // sub sp, #16
+            0x2001, // movs r0, #1
+        ]);
+        assert_eq!(thumb_function_start(&code, 6 * 2, MAX_PROLOGUE_DISTANCE), Some(2));
+    }
+
+    #[test]
+    fn thumb_wide_push() {
+        let code = thumb(&[
+            0x4770, // bx lr
+            0xE92D, 0x4FF0, // push.w {r4-r11, lr}
+            0xB081, // sub sp, #4
+            0xF8D0, 0xB500, // ldr.w r11, [r0, #0x500]
+            0x2001, // movs r0, #1
+        ]);
+        // the second halfword of the ldr.w looks like a push but must be skipped
+        assert_eq!(thumb_function_start(&code, 6 * 2, MAX_PROLOGUE_DISTANCE), Some(2));
+    }
+
+    #[test]
+    fn thumb_stops_at_the_previous_function() {
+        let code = thumb(&[
+            0xB580, // push {r7, lr}
+            0xAF00, // add r7, sp, #0
+            0xF000, 0xF800, // bl #4
+            0xBD80, // pop {r7, pc}
+            0x1840, // adds r0, r0, r1
+            0x4770, // bx lr
+            0x4408, // add r0, r1
+        ]);
+        assert_eq!(thumb_function_start(&code, 5 * 2, MAX_PROLOGUE_DISTANCE), None);
+        assert_eq!(thumb_function_start(&code, 7 * 2, MAX_PROLOGUE_DISTANCE), None);
+        assert_eq!(thumb_function_start(&code, 4 * 2, MAX_PROLOGUE_DISTANCE), Some(0));
+    }
+
+    #[test]
+    fn thumb_walks_past_branches_in_the_body() {
+        let code = thumb(&[
+            0x4770, // bx lr
+            0xB580, // push {r7, lr}
+            0xAF00, // add r7, sp, #0
+            0xB108, // cbz r0, #6
+            0x2001, // movs r0, #1
+            0xE001, // b #6, joins the if and the else
+            0x2002, // movs r0, #2
+            0xF000, 0xB802, // b.w #8
+            0xE7F8, // b #-12, a loop back-edge
+            0xF000, 0xF800, // bl #4
+        ]);
+        assert_eq!(thumb_function_start(&code, 10 * 2, MAX_PROLOGUE_DISTANCE), Some(2));
+        assert_eq!(thumb_function_start(&code, 6 * 2, MAX_PROLOGUE_DISTANCE), Some(2));
+    }
+
+    #[test]
+    fn x86_aligned_prologue_after_padding() {
+        let mut code = vec![0x31, 0xC0, 0xC3]; // xor eax, eax; ret
+        code.resize(16, 0xCC); // int3
+        code.extend([0x41, 0x57, 0x41, 0x56, 0x53]); // push r15; push r14; push rbx
+        code.extend([0x48, 0x8D, 0x05, 0x00, 0x00, 0x00, 0x00]); // lea rax, [rip]
+        code.extend([0xE8, 0x00, 0x00, 0x00, 0x00, 0x90]); // call; nop
+        code.extend([0x55, 0x48, 0x89, 0xE5]); // push rbp; mov rbp, rsp, not aligned
+
+        assert_eq!(x86_function_start(&code, 21, MAX_PROLOGUE_DISTANCE), Some(16));
+        assert_eq!(x86_function_start(&code, 34, MAX_PROLOGUE_DISTANCE), Some(16));
+        assert_eq!(x86_function_start(&code, 2, MAX_PROLOGUE_DISTANCE), None);
+        // offsets past the code are clamped to its end
+        assert_eq!(x86_function_start(&code, code.len() + 0x10, MAX_PROLOGUE_DISTANCE), Some(16));
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use super::overrides::DefinitionError;
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub invalidations: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MatchInfo {
+    // virtual address in the module
+    pub offset: usize,
+    pub region_start: usize,
+    pub region_end: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Ambiguity {
+    pub signature: String,
+    pub candidate: usize,
+    pub pattern: String,
+    pub matches: Vec<MatchInfo>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum UnresolvedReason {
+    NotFound,
+    // the scan budget ran out before the signature was found
+    TimedOut,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Unresolved {
+    pub signature: String,
+    pub reason: UnresolvedReason,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SignatureTiming {
+    pub signature: String,
+    pub elapsed_us: u64,
+    pub resolved: bool,
+}
+
+// summary of the signature resolution, sent back to java on request
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SignatureReport {
+    pub cache: CacheStats,
+    pub ambiguities: Vec<Ambiguity>,
+    pub unresolved: Vec<Unresolved>,
+    pub timings: Vec<SignatureTiming>,
+    pub prefetch_us: u64,
+    pub budget_exhausted: bool,
+    // the definitions supplied at init are ignored when any error is reported
+    pub definition_errors: Vec<DefinitionError>,
+}
+
+static REPORT: Mutex<Option<SignatureReport>> = Mutex::new(None);
+
+pub fn record(block: impl FnOnce(&mut SignatureReport)) {
+    block(REPORT.lock().unwrap().get_or_insert_with(SignatureReport::default));
+}
+
+pub fn report() -> SignatureReport {
+    REPORT.lock().unwrap().clone().unwrap_or_default()
+}
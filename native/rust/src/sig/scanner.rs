@@ -0,0 +1,301 @@
+use std::{sync::atomic::{AtomicBool, AtomicUsize, Ordering}, thread, time::Instant};
+
+use aho_corasick::{AhoCorasick, AhoCorasickKind};
+use memchr::memmem::Finder;
+
+use super::signature::Signature;
+
+// anchors on the longest solid run of the pattern and only checks the masked bytes at the candidates
+pub fn scan(haystack: &[u8], pattern: &Signature, once: bool) -> Vec<usize> {
+    let mut results = Vec::new();
+
+    if pattern.is_empty() || pattern.len() > haystack.len() {
+        return results;
+    }
+
+    let anchor = pattern.anchor();
+
+    if anchor.is_empty() {
+        let candidates = (0..=haystack.len() - pattern.len()).filter(|&offset| pattern.matches_at(haystack, offset));
+        return if once { candidates.take(1).collect() } else { candidates.collect() };
+    }
+
+    let anchor_offset = pattern.anchor_offset();
+    let finder = Finder::new(anchor);
+    let mut position = anchor_offset;
+
+    // matches can overlap, so restart the search right after each candidate
+    while let Some(found) = finder.find(&haystack[position..]) {
+        let candidate = position + found - anchor_offset;
+
+        if pattern.matches_at(haystack, candidate) {
+            results.push(candidate);
+            if once {
+                break;
+            }
+        }
+
+        position += found + 1;
+        if position >= haystack.len() {
+            break;
+        }
+    }
+
+    results
+}
+
+// automaton over the pattern anchors, built once and run over any number of haystacks
+pub struct MultiScanner<'a> {
+    patterns: &'a [Signature],
+    automaton: Option<AhoCorasick>,
+    // patterns verified from each automaton match, patterns sharing the same anchor share the match
+    anchor_patterns: Vec<Vec<usize>>,
+    // patterns without a fully specified byte, scanned one by one
+    unanchored: Vec<usize>,
+}
+
+impl<'a> MultiScanner<'a> {
+    pub fn new(patterns: &'a [Signature]) -> Self {
+        let mut anchors: Vec<&[u8]> = Vec::new();
+        let mut anchor_patterns: Vec<Vec<usize>> = Vec::new();
+        let mut unanchored = Vec::new();
+
+        for (index, pattern) in patterns.iter().enumerate() {
+            if pattern.is_empty() {
+                continue;
+            }
+
+            if pattern.anchor().is_empty() {
+                unanchored.push(index);
+                continue;
+            }
+
+            if let Some(existing) = anchors.iter().position(|anchor| *anchor == pattern.anchor()) {
+                anchor_patterns[existing].push(index);
+            } else {
+                anchors.push(pattern.anchor());
+                anchor_patterns.push(vec![index]);
+            }
+        }
+
+        let automaton = (!anchors.is_empty()).then(|| {
+            AhoCorasick::builder()
+                .kind(Some(AhoCorasickKind::DFA))
+                .build(&anchors)
+                .expect("Failed to build signature automaton")
+        });
+
+        Self { patterns, automaton, anchor_patterns, unanchored }
+    }
+
+    pub fn scan(&self, haystack: &[u8], once: bool) -> Vec<Vec<usize>> {
+        let mut results = vec![Vec::new(); self.patterns.len()];
+
+        for &index in &self.unanchored {
+            results[index] = scan(haystack, &self.patterns[index], once);
+        }
+
+        let Some(automaton) = &self.automaton else {
+            return results;
+        };
+        let mut remaining = self.anchor_patterns.iter().map(|indices| indices.len()).sum::<usize>();
+
+        for found in automaton.find_overlapping_iter(haystack) {
+            for &index in &self.anchor_patterns[found.pattern().as_usize()] {
+                let pattern = &self.patterns[index];
+
+                if once && !results[index].is_empty() {
+                    continue;
+                }
+
+                let Some(candidate) = found.start().checked_sub(pattern.anchor_offset()) else {
+                    continue;
+                };
+
+                if pattern.matches_at(haystack, candidate) {
+                    results[index].push(candidate);
+                    if once {
+                        remaining -= 1;
+                    }
+                }
+            }
+
+            if once && remaining == 0 {
+                break;
+            }
+        }
+
+        results
+    }
+}
+
+// resolves every pattern in a single pass by running an automaton over the pattern anchors
+pub fn scan_many(haystack: &[u8], patterns: &[Signature], once: bool) -> Vec<Vec<usize>> {
+    MultiScanner::new(patterns).scan(haystack, once)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanTimedOut;
+
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+/*
+ * scans the haystacks in chunks spread over the available cores, returns the offsets of every pattern in every haystack
+ * - chunks overlap by the longest pattern so matches crossing a chunk boundary are kept, by the chunk they start in
+ * - chunks start at multiples of CHUNK_SIZE so pattern alignments stay relative to the haystack
+ * - the workers stop taking chunks once the deadline has passed
+ */
+
+pub fn scan_parallel(haystacks: &[&[u8]], patterns: &[Signature], deadline: Option<Instant>) -> Result<Vec<Vec<Vec<usize>>>, ScanTimedOut> {
+    let scanner = MultiScanner::new(patterns);
+    let overlap = patterns.iter().map(Signature::len).max().unwrap_or(0).saturating_sub(1);
+
+    let chunks = haystacks.iter().enumerate().flat_map(|(index, haystack)| {
+        (0..haystack.len()).step_by(CHUNK_SIZE).map(move |start| (index, start))
+    }).collect::<Vec<_>>();
+
+    let next_chunk = AtomicUsize::new(0);
+    let timed_out = AtomicBool::new(false);
+    let workers = thread::available_parallelism().map_or(1, |count| count.get()).min(chunks.len()).max(1);
+
+    let found = thread::scope(|scope| {
+        let workers = (0..workers).map(|_| scope.spawn(|| {
+            let mut found = Vec::new();
+
+            loop {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    timed_out.store(true, Ordering::Relaxed);
+                }
+                if timed_out.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let Some(&(haystack_index, start)) = chunks.get(next_chunk.fetch_add(1, Ordering::Relaxed)) else {
+                    break;
+                };
+                let haystack = haystacks[haystack_index];
+                let chunk = &haystack[start..(start + CHUNK_SIZE + overlap).min(haystack.len())];
+
+                for (pattern_index, offsets) in scanner.scan(chunk, false).into_iter().enumerate() {
+                    found.extend(offsets.into_iter().filter(|&offset| offset < CHUNK_SIZE).map(|offset| (haystack_index, pattern_index, start + offset)));
+                }
+            }
+
+            found
+        })).collect::<Vec<_>>();
+
+        workers.into_iter().flat_map(|worker| worker.join().expect("Signature scan worker panicked")).collect::<Vec<_>>()
+    });
+
+    if timed_out.load(Ordering::Relaxed) {
+        return Err(ScanTimedOut);
+    }
+
+    let mut results = vec![vec![Vec::new(); patterns.len()]; haystacks.len()];
+    for (haystack_index, pattern_index, offset) in found {
+        results[haystack_index][pattern_index].push(offset);
+    }
+    results.iter_mut().flatten().for_each(|offsets| offsets.sort_unstable());
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    // reference implementation checking every offset of the haystack
+    fn naive_scan(haystack: &[u8], pattern: &Signature) -> Vec<usize> {
+        (0..haystack.len()).filter(|&offset| {
+            pattern.alignment().is_none_or(|alignment| offset % alignment == 0)
+                && haystack.get(offset..offset + pattern.len()).is_some_and(|window| {
+                    window.iter().zip(pattern.bytes()).zip(pattern.mask()).all(|((byte, expected), mask)| byte & mask == *expected)
+                })
+        }).collect()
+    }
+
+    // patterns taken from the haystack so they match at least once, with random wildcard nibbles and alignment
+    fn random_patterns(rng: &mut StdRng, haystack: &[u8], count: usize) -> Vec<Signature> {
+        (0..count).map(|_| {
+            let length = rng.gen_range(1..=12.min(haystack.len()));
+            let start = rng.gen_range(0..=haystack.len() - length);
+            let mask = (0..length).map(|_| [0xFF, 0xFF, 0xFF, 0xF0, 0x0F, 0x00][rng.gen_range(0..6)]).collect::<Vec<u8>>();
+            let alignment = [None, None, Some(2), Some(4)][rng.gen_range(0..4)];
+            Signature::new(haystack[start..start + length].to_vec(), mask, alignment)
+        }).collect()
+    }
+
+    fn signatures(patterns: &[&str]) -> Vec<Signature> {
+        patterns.iter().map(|pattern| pattern.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn parallel_scan_keeps_matches_across_chunks() {
+        let patterns = signatures(&["DE AD BE EF", "CA FE ?? BA align=4"]);
+        let mut first = vec![0u8; CHUNK_SIZE * 3 + 17];
+        let second = vec![0u8; 64];
+
+        for offset in [0, CHUNK_SIZE - 2, CHUNK_SIZE * 2 + 5, first.len() - 4] {
+            first[offset..offset + 4].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        }
+        for offset in [CHUNK_SIZE - 7, CHUNK_SIZE + 8] {
+            first[offset..offset + 4].copy_from_slice(&[0xCA, 0xFE, 0x00, 0xBA]);
+        }
+
+        let results = scan_parallel(&[&first, &second], &patterns, None).unwrap();
+        assert_eq!(results[0][0], vec![0, CHUNK_SIZE - 2, CHUNK_SIZE * 2 + 5, first.len() - 4]);
+        assert_eq!(results[0][1], vec![CHUNK_SIZE + 8]);
+        assert_eq!(results[0], patterns.iter().map(|pattern| scan(&first, pattern, false)).collect::<Vec<_>>());
+        assert_eq!(results[1], vec![Vec::<usize>::new(); 2]);
+    }
+
+    #[test]
+    fn parallel_scan_stops_at_deadline() {
+        let patterns = signatures(&["DE AD BE EF"]);
+        let haystack = vec![0u8; CHUNK_SIZE * 2];
+        let deadline = Instant::now() - Duration::from_millis(1);
+
+        assert_eq!(scan_parallel(&[&haystack], &patterns, Some(deadline)), Err(ScanTimedOut));
+        assert!(scan_parallel(&[&haystack], &patterns, Some(Instant::now() + Duration::from_secs(60))).is_ok());
+    }
+
+    #[test]
+    fn scanners_agree_with_naive_scan() {
+        let mut rng = StdRng::seed_from_u64(0x5EED);
+
+        for _ in 0..200 {
+            // a small alphabet makes overlapping and repeated matches common
+            let haystack = (0..rng.gen_range(1..2048)).map(|_| [0x00, 0x01, 0xA5, 0xFF][rng.gen_range(0..4)]).collect::<Vec<u8>>();
+            let patterns = random_patterns(&mut rng, &haystack, 6);
+            let expected = patterns.iter().map(|pattern| naive_scan(&haystack, pattern)).collect::<Vec<_>>();
+
+            assert_eq!(patterns.iter().map(|pattern| scan(&haystack, pattern, false)).collect::<Vec<_>>(), expected);
+            assert_eq!(scan_many(&haystack, &patterns, false), expected);
+            for (pattern, expected) in patterns.iter().zip(&expected) {
+                assert_eq!(scan(&haystack, pattern, true), expected.iter().take(1).copied().collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_scan_agrees_with_naive_scan() {
+        let mut rng = StdRng::seed_from_u64(0xC4A2);
+        let mut haystack = (0..CHUNK_SIZE * 2 + 100).map(|_| rng.gen::<u8>()).collect::<Vec<u8>>();
+        let patterns = random_patterns(&mut rng, &haystack[..4096], 4);
+
+        // plant every pattern across each chunk boundary
+        for (index, pattern) in patterns.iter().enumerate() {
+            for boundary in [CHUNK_SIZE, CHUNK_SIZE * 2] {
+                let offset = boundary - index - 1;
+                haystack[offset..offset + pattern.len()].copy_from_slice(pattern.bytes());
+            }
+        }
+
+        let expected = patterns.iter().map(|pattern| naive_scan(&haystack, pattern)).collect::<Vec<_>>();
+        assert_eq!(scan_parallel(&[&haystack], &patterns, None).unwrap()[0], expected);
+    }
+}
@@ -0,0 +1,359 @@
+use std::{error::Error, fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    Empty,
+    InvalidToken { index: usize, token: String },
+    NoFixedByte,
+    UnknownOption(String),
+    InvalidAlignment(String),
+    InvalidOperand(String),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Empty => write!(f, "signature is empty"),
+            SignatureError::InvalidToken { index, token } => write!(f, "invalid token {:?} at index {}", token, index),
+            SignatureError::NoFixedByte => write!(f, "signature has no fully specified byte"),
+            SignatureError::UnknownOption(option) => write!(f, "unknown option {:?}", option),
+            SignatureError::InvalidAlignment(value) => write!(f, "invalid alignment {:?}, expected a power of two", value),
+            SignatureError::InvalidOperand(operand) => write!(f, "invalid operand {:?}", operand),
+        }
+    }
+}
+
+impl Error for SignatureError {}
+
+// arm64 instruction operands resolved at match time, offsets are relative to the start of the match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    // the signature resolves to the target of the BL at the offset
+    Branch { offset: i64 },
+    // the ADRP + ADD/LDR pair at the offset must point to the target, relative to the module load base
+    PageAddress { offset: i64, target: u64 },
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = |offset: i64| if offset < 0 { "-" } else { "+" };
+        match self {
+            Operand::Branch { offset } => write!(f, "bl={}{:#x}", sign(*offset), offset.unsigned_abs()),
+            Operand::PageAddress { offset, target } => write!(f, "adrp={}{:#x}:{:#x}", sign(*offset), offset.unsigned_abs(), target),
+        }
+    }
+}
+
+/*
+ * byte pattern such as "FF 00 A? ?? align=4"
+ * - "AB" matches a byte, "A?" / "?B" match a single nibble, "?" and "??" match any byte
+ * - "align=N" only accepts matches whose offset is a multiple of N
+ * - "bl=+0x10" follows the BL 0x10 bytes after the start of the match
+ * - "adrp=+0x8:0x1234" requires the ADRP + ADD/LDR pair 8 bytes after the start of the match to point to 0x1234
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    bytes: Vec<u8>,
+    mask: Vec<u8>,
+    alignment: Option<usize>,
+    operands: Vec<Operand>,
+    // longest run of fully specified bytes (start, length)
+    anchor: (usize, usize),
+}
+
+impl Signature {
+    pub fn new(bytes: Vec<u8>, mask: Vec<u8>, alignment: Option<usize>) -> Self {
+        let bytes = bytes.iter().zip(&mask).map(|(byte, mask)| byte & mask).collect();
+        let mut anchor = (0, 0);
+        let mut run_start = 0;
+
+        for (i, &mask) in mask.iter().enumerate() {
+            if mask != 0xFF {
+                run_start = i + 1;
+                continue;
+            }
+            if i + 1 - run_start > anchor.1 {
+                anchor = (run_start, i + 1 - run_start);
+            }
+        }
+
+        Self { bytes, mask, alignment, operands: Vec::new(), anchor }
+    }
+
+    pub fn with_operands(mut self, operands: Vec<Operand>) -> Self {
+        self.operands = operands;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn mask(&self) -> &[u8] {
+        &self.mask
+    }
+
+    pub fn alignment(&self) -> Option<usize> {
+        self.alignment
+    }
+
+    pub fn operands(&self) -> &[Operand] {
+        &self.operands
+    }
+
+    // offset of the BL the signature resolves through
+    pub fn branch(&self) -> Option<i64> {
+        self.operands.iter().find_map(|operand| match operand {
+            Operand::Branch { offset } => Some(*offset),
+            _ => None,
+        })
+    }
+
+    pub fn anchor(&self) -> &[u8] {
+        &self.bytes[self.anchor.0..self.anchor.0 + self.anchor.1]
+    }
+
+    pub fn anchor_offset(&self) -> usize {
+        self.anchor.0
+    }
+
+    pub fn matches_at(&self, haystack: &[u8], offset: usize) -> bool {
+        if self.alignment.is_some_and(|alignment| !offset.is_multiple_of(alignment)) {
+            return false;
+        }
+
+        let Some(window) = haystack.get(offset..offset + self.bytes.len()) else {
+            return false;
+        };
+
+        window.iter().zip(&self.bytes).zip(&self.mask).all(|((byte, expected), mask)| byte & mask == *expected)
+    }
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.strip_prefix("0x").or_else(|| value.strip_prefix("0X"))?, 16).ok()
+}
+
+// "+0x10" or "-0x8"
+fn parse_offset(value: &str) -> Option<i64> {
+    let (negative, value) = match value.split_at_checked(1)? {
+        ("+", value) => (false, value),
+        ("-", value) => (true, value),
+        _ => return None,
+    };
+    let offset = i64::try_from(parse_hex(value)?).ok()?;
+    Some(if negative { -offset } else { offset })
+}
+
+fn parse_operand(key: &str, value: &str) -> Option<Operand> {
+    match key {
+        "bl" => Some(Operand::Branch { offset: parse_offset(value)? }),
+        "adrp" => {
+            let (offset, target) = value.split_once(':')?;
+            Some(Operand::PageAddress { offset: parse_offset(offset)?, target: parse_hex(target)? })
+        }
+        _ => None,
+    }
+}
+
+fn parse_nibble(c: char) -> Option<(u8, u8)> {
+    match c {
+        '?' => Some((0, 0)),
+        _ => c.to_digit(16).map(|digit| (digit as u8, 0xF)),
+    }
+}
+
+impl FromStr for Signature {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = Vec::new();
+        let mut mask = Vec::new();
+        let mut alignment = None;
+        let mut operands = Vec::new();
+
+        for (index, token) in s.split_whitespace().enumerate() {
+            if let Some((key, value)) = token.split_once('=') {
+                match key {
+                    "align" => {
+                        let parsed = value.parse::<usize>().ok().filter(|value| value.is_power_of_two());
+                        alignment = Some(parsed.ok_or_else(|| SignatureError::InvalidAlignment(value.to_string()))?);
+                    }
+                    "bl" | "adrp" => {
+                        let operand = parse_operand(key, value).ok_or_else(|| SignatureError::InvalidOperand(token.to_string()))?;
+                        // a signature can only resolve through a single branch
+                        if matches!(operand, Operand::Branch { .. }) && operands.iter().any(|operand| matches!(operand, Operand::Branch { .. })) {
+                            return Err(SignatureError::InvalidOperand(token.to_string()));
+                        }
+                        operands.push(operand);
+                    }
+                    _ => return Err(SignatureError::UnknownOption(token.to_string())),
+                }
+                continue;
+            }
+
+            let invalid_token = || SignatureError::InvalidToken { index, token: token.to_string() };
+            let chars = token.chars().collect::<Vec<_>>();
+
+            let (byte, byte_mask) = match chars.as_slice() {
+                ['?'] => (0, 0),
+                [high, low] => {
+                    let (high, high_mask) = parse_nibble(*high).ok_or_else(invalid_token)?;
+                    let (low, low_mask) = parse_nibble(*low).ok_or_else(invalid_token)?;
+                    (high << 4 | low, high_mask << 4 | low_mask)
+                }
+                _ => return Err(invalid_token()),
+            };
+
+            bytes.push(byte);
+            mask.push(byte_mask);
+        }
+
+        if bytes.is_empty() {
+            return Err(SignatureError::Empty);
+        }
+
+        if !mask.contains(&0xFF) {
+            return Err(SignatureError::NoFixedByte);
+        }
+
+        Ok(Self::new(bytes, mask, alignment).with_operands(operands))
+    }
+}
+
+impl fmt::Display for Signature {
+    // canonical form, also used as the signature cache key
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (byte, mask)) in self.bytes.iter().zip(&self.mask).enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            match mask {
+                0xFF => write!(f, "{:02X}", byte)?,
+                0xF0 => write!(f, "{:X}?", byte >> 4)?,
+                0x0F => write!(f, "?{:X}", byte & 0xF)?,
+                _ => write!(f, "??")?,
+            }
+        }
+
+        if let Some(alignment) = self.alignment {
+            write!(f, " align={}", alignment)?;
+        }
+
+        for operand in &self.operands {
+            write!(f, " {}", operand)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Signature, SignatureError> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_fixed_bytes() {
+        let signature = parse("FF 00 a9 3f").unwrap();
+        assert_eq!(signature.bytes(), &[0xFF, 0x00, 0xA9, 0x3F]);
+        assert_eq!(signature.mask(), &[0xFF; 4]);
+        assert_eq!(signature.to_string(), "FF 00 A9 3F");
+    }
+
+    #[test]
+    fn parses_wildcards() {
+        let signature = parse("FF ? ?? A? ?b").unwrap();
+        assert_eq!(signature.mask(), &[0xFF, 0x00, 0x00, 0xF0, 0x0F]);
+        assert_eq!(signature.bytes(), &[0xFF, 0x00, 0x00, 0xA0, 0x0B]);
+        assert_eq!(signature.to_string(), "FF ?? ?? A? ?B");
+    }
+
+    #[test]
+    fn normalizes_whitespace() {
+        assert_eq!(parse("  FF  00\t?? 94 ").unwrap().to_string(), "FF 00 ?? 94");
+    }
+
+    #[test]
+    fn canonical_form_round_trips() {
+        for s in ["FF FF 00 A9 3F 00 00 F9", "00 E4 ?? 6F 2? ?0 align=4", "9A align=2"] {
+            assert_eq!(parse(s).unwrap().to_string(), s);
+            assert_eq!(parse(&parse(s).unwrap().to_string()).unwrap(), parse(s).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        assert_eq!(parse(""), Err(SignatureError::Empty));
+        assert_eq!(parse("   "), Err(SignatureError::Empty));
+        assert_eq!(parse("?? ?"), Err(SignatureError::NoFixedByte));
+        assert_eq!(parse("A? ?B"), Err(SignatureError::NoFixedByte));
+        assert_eq!(parse("FF GG"), Err(SignatureError::InvalidToken { index: 1, token: "GG".into() }));
+        assert_eq!(parse("FF0 01"), Err(SignatureError::InvalidToken { index: 0, token: "FF0".into() }));
+        assert_eq!(parse("F 01"), Err(SignatureError::InvalidToken { index: 0, token: "F".into() }));
+        assert_eq!(parse("FF ???"), Err(SignatureError::InvalidToken { index: 1, token: "???".into() }));
+        assert_eq!(parse("FF é1"), Err(SignatureError::InvalidToken { index: 1, token: "é1".into() }));
+        assert_eq!(parse("FF size=4"), Err(SignatureError::UnknownOption("size=4".into())));
+        assert_eq!(parse("FF align=3"), Err(SignatureError::InvalidAlignment("3".into())));
+        assert_eq!(parse("FF align=0"), Err(SignatureError::InvalidAlignment("0".into())));
+        assert_eq!(parse("FF align=x"), Err(SignatureError::InvalidAlignment("x".into())));
+    }
+
+    #[test]
+    fn matches_nibbles() {
+        let signature = parse("FF A? ?B").unwrap();
+        assert!(signature.matches_at(&[0xFF, 0xA7, 0x3B], 0));
+        assert!(signature.matches_at(&[0x00, 0xFF, 0xAF, 0x0B], 1));
+        assert!(!signature.matches_at(&[0xFF, 0xB7, 0x3B], 0));
+        assert!(!signature.matches_at(&[0xFF, 0xA7, 0x3C], 0));
+        assert!(!signature.matches_at(&[0xFF, 0xA7], 0));
+    }
+
+    #[test]
+    fn matches_alignment() {
+        let signature = parse("C0 03 5F D6 align=4").unwrap();
+        let haystack = [0, 0xC0, 0x03, 0x5F, 0xD6, 0, 0, 0, 0xC0, 0x03, 0x5F, 0xD6];
+        assert!(!signature.matches_at(&haystack, 1));
+        assert!(signature.matches_at(&haystack, 8));
+    }
+
+    #[test]
+    fn parses_operands() {
+        let signature = parse("E0 03 13 AA ?? ?? ?? 94 ?? ?? ?? ?0 bl=+0x4 adrp=+0x8:0x2A0 align=4").unwrap();
+        assert_eq!(signature.operands(), &[
+            Operand::Branch { offset: 4 },
+            Operand::PageAddress { offset: 8, target: 0x2A0 },
+        ]);
+        assert_eq!(signature.branch(), Some(4));
+        assert_eq!(signature.to_string(), "E0 03 13 AA ?? ?? ?? 94 ?? ?? ?? ?0 align=4 bl=+0x4 adrp=+0x8:0x2a0");
+        assert_eq!(parse("94 bl=-0x10").unwrap().branch(), Some(-0x10));
+        assert_eq!(parse("94").unwrap().branch(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_operands() {
+        assert_eq!(parse("94 bl=0x10"), Err(SignatureError::InvalidOperand("bl=0x10".into())));
+        assert_eq!(parse("94 bl=+16"), Err(SignatureError::InvalidOperand("bl=+16".into())));
+        assert_eq!(parse("94 adrp=+0x8"), Err(SignatureError::InvalidOperand("adrp=+0x8".into())));
+        assert_eq!(parse("94 adrp=+0x8:zz"), Err(SignatureError::InvalidOperand("adrp=+0x8:zz".into())));
+        assert_eq!(parse("94 bl=+0x4 bl=+0x8"), Err(SignatureError::InvalidOperand("bl=+0x8".into())));
+    }
+
+    #[test]
+    fn anchors_on_longest_fixed_run() {
+        let signature = parse("FF ?? 01 02 03 A? 04 05").unwrap();
+        assert_eq!(signature.anchor(), &[0x01, 0x02, 0x03]);
+        assert_eq!(signature.anchor_offset(), 2);
+    }
+}
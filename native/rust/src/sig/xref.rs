@@ -0,0 +1,200 @@
+use memchr::memmem;
+use procfs::process::MMPermissions;
+
+use crate::{mapped_lib::MappedLib, memory::{read_u16, read_u32}};
+
+use super::{arm64, definition::Arch, executable_regions, find_function_start};
+
+// maximum number of instructions between the address load and the instruction completing it
+const XREF_WINDOW: usize = 8;
+
+// offsets of the whole string, the tail of a longer string ending with the literal is not a match
+fn literal_offsets(haystack: &[u8], finder: &memmem::Finder) -> Vec<usize> {
+    finder.find_iter(haystack).filter(|&offset| offset == 0 || haystack[offset - 1] == 0).collect()
+}
+
+// addresses of the NUL terminated literal in the read-only regions
+pub fn find_literals(mapped_lib: &MappedLib, literal: &str) -> Vec<usize> {
+    let needle = [literal.as_bytes(), b"\0"].concat();
+    let finder = memmem::Finder::new(&needle);
+
+    mapped_lib.regions.iter().filter(|region| {
+        region.perms.contains(MMPermissions::READ) && !region.perms.contains(MMPermissions::WRITE) && region.end > region.start
+    }).flat_map(|region| {
+        literal_offsets(region.bytes(), &finder).into_iter().map(|offset| region.start as usize + offset).collect::<Vec<_>>()
+    }).collect()
+}
+
+// offsets of the ADRP instructions followed by an ADD completing one of the target addresses
+pub fn arm64_references(code: &[u8], base: u64, targets: &[u64]) -> Vec<usize> {
+    (0..code.len().saturating_sub(3)).step_by(4).filter(|&offset| {
+        let Some((register, page)) = read_u32(code, offset).and_then(|insn| arm64::decode_adrp(insn, base + offset as u64)) else {
+            return false;
+        };
+
+        targets.iter().filter(|&&target| target & !0xFFF == page).any(|&target| {
+            (1..=XREF_WINDOW).filter_map(|index| read_u32(code, offset + index * 4)).any(|insn| {
+                arm64::decode_add_immediate(insn).is_some_and(|(source, immediate)| source == register && immediate == target & 0xFFF)
+            })
+        })
+    }).collect()
+}
+
+// LDR Rt, [PC, #imm] and LDR.W Rt, [PC, #imm], returns the register and the offset of the literal word
+fn thumb_pc_relative_load(code: &[u8], offset: usize) -> Option<(u16, usize)> {
+    let halfword = read_u16(code, offset)?;
+    let literal_base = (offset + 4) & !3;
+
+    if halfword & 0xF800 == 0x4800 {
+        return Some(((halfword >> 8) & 0x7, literal_base + (halfword & 0xFF) as usize * 4));
+    }
+
+    if halfword == 0xF8DF {
+        let operands = read_u16(code, offset + 2)?;
+        return Some((operands >> 12, literal_base + (operands & 0xFFF) as usize));
+    }
+
+    None
+}
+
+// ADD Rdn, PC
+fn thumb_add_pc(register: u16) -> u16 {
+    0x4478 | (register & 0x8) << 4 | register & 0x7
+}
+
+// offsets of the PC-relative loads whose literal, once added to the PC, gives one of the target addresses
+pub fn thumb_references(code: &[u8], base: u32, targets: &[u32]) -> Vec<usize> {
+    (0..code.len().saturating_sub(1)).step_by(2).filter(|&offset| {
+        let Some((register, literal_offset)) = thumb_pc_relative_load(code, offset) else {
+            return false;
+        };
+        let Some(literal) = read_u32(code, literal_offset) else {
+            return false;
+        };
+
+        (1..=XREF_WINDOW).map(|index| offset + index * 2).any(|position| {
+            read_u16(code, position) == Some(thumb_add_pc(register))
+                && targets.contains(&literal.wrapping_add(base.wrapping_add(position as u32 + 4)))
+        })
+    }).collect()
+}
+
+// offsets of the LEA r64, [RIP + disp32] whose displacement, relative to the next instruction, gives one of the target addresses
+pub fn x86_64_references(code: &[u8], base: u64, targets: &[u64]) -> Vec<usize> {
+    (0..code.len().saturating_sub(6)).filter(|&offset| {
+        let (rex, opcode, modrm) = (code[offset], code[offset + 1], code[offset + 2]);
+        if !matches!(rex, 0x48 | 0x4C) || opcode != 0x8D || modrm & 0xC7 != 0x05 {
+            return false;
+        }
+
+        let displacement = read_u32(code, offset + 3).unwrap() as i32 as i64;
+        targets.contains(&(base + offset as u64 + 7).wrapping_add_signed(displacement))
+    }).collect()
+}
+
+// entry points of the functions referencing the string literal, in address order
+pub fn find_string_references(mapped_lib: &MappedLib, literal: &str, arch: Arch) -> Vec<usize> {
+    // x86 reaches its literals through the GOT register, which isn't tracked
+    if arch == Arch::X86 {
+        debug!("String references are not supported on {:?}", arch);
+        return Vec::new();
+    }
+
+    let literals = find_literals(mapped_lib, literal);
+
+    if literals.is_empty() {
+        debug!("String literal {:?} not found", literal);
+        return Vec::new();
+    }
+
+    let references = executable_regions(mapped_lib).into_iter().flat_map(|region| {
+        let code = region.bytes();
+        let offsets = match arch {
+            Arch::Arm64 => arm64_references(code, region.start, &literals.iter().map(|&address| address as u64).collect::<Vec<_>>()),
+            Arch::Arm => thumb_references(code, region.start as u32, &literals.iter().map(|&address| address as u32).collect::<Vec<_>>()),
+            Arch::X86_64 => x86_64_references(code, region.start, &literals.iter().map(|&address| address as u64).collect::<Vec<_>>()),
+            Arch::X86 => Vec::new(),
+        };
+        offsets.into_iter().map(|offset| region.start as usize + offset).collect::<Vec<_>>()
+    }).collect::<Vec<_>>();
+
+    let mut functions = references.iter().filter_map(|&reference| {
+        let function_start = find_function_start(mapped_lib, reference, arch);
+        if function_start.is_none() {
+            debug!("No function prologue before the reference to {:?} at {:#x}", literal, reference);
+        }
+        function_start
+    }).collect::<Vec<_>>();
+
+    functions.sort_unstable();
+    functions.dedup();
+    functions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig::fixtures::{arm64, thumb};
+
+    #[test]
+    fn matches_whole_literals() {
+        let finder = memmem::Finder::new(b"RTRIM\0");
+        assert_eq!(literal_offsets(b"RTRIM\0LTRIM\0NORTRIM\0\0RTRIM\0", &finder), vec![0, 21]);
+    }
+
+    #[test]
+    fn finds_arm64_references() {
+        let code = arm64(&[
+            0xD0000000, // adrp x0, #0x2000
+            0xAA1303E1, // mov x1, x19
+            0x91048C00, // add x0, x0, #0x123
+            0xD0000001, // adrp x1, #0x2000
+            0x91048C00, // add x0, x0, #0x123
+            0x91049021, // add x1, x1, #0x124
+        ]);
+        assert_eq!(arm64_references(&code, 0x10000, &[0x12123]), vec![0]);
+        assert_eq!(arm64_references(&code, 0x10000, &[0x12124]), vec![12]);
+        assert!(arm64_references(&code, 0x10000, &[0x13123]).is_empty());
+    }
+
+    #[test]
+    fn finds_x86_64_references() {
+        let code = [
+            0x48, 0x8D, 0x3D, 0xF9, 0x0F, 0x00, 0x00, // lea rdi, [rip + 0xFF9]
+            0x4C, 0x8D, 0x05, 0xF2, 0xFF, 0xFF, 0xFF, // lea r8, [rip - 0xE]
+            0x48, 0x8D, 0x47, 0x10, // lea rax, [rdi + 0x10]
+        ];
+        assert_eq!(x86_64_references(&code, 0x1000, &[0x2000]), vec![0]);
+        assert_eq!(x86_64_references(&code, 0x1000, &[0x1000]), vec![7]);
+        assert!(x86_64_references(&code, 0x1000, &[0x1017]).is_empty());
+    }
+
+    #[test]
+    fn finds_thumb_references() {
+        let code = thumb(&[
+            0x4802, // ldr r0, [pc, #8]
+            0x2101, // movs r1, #1
+            0x4478, // add r0, pc
+            0x4770, // bx lr
+            0x2000, // movs r0, #0
+            0x4770, // bx lr
+            0x0F00, 0x0000, // .word 0xF00
+        ]);
+        // the literal is relative to the pc of the add, 0x1000 + 4 + 4
+        assert_eq!(thumb_references(&code, 0x1000, &[0x1F08]), vec![0]);
+        assert!(thumb_references(&code, 0x1000, &[0x1F00]).is_empty());
+    }
+
+    #[test]
+    fn finds_thumb_wide_references() {
+        let code = thumb(&[
+            0xF8DF, 0x9008, // ldr.w r9, [pc, #8]
+            0x44F9, // add r9, pc
+            0x4770, // bx lr
+            0xBF00, // nop
+            0xBF00, // nop
+            0x0100, 0x0000, // .word 0x100
+        ]);
+        assert_eq!(thumb_references(&code, 0x2000, &[0x2108]), vec![0]);
+    }
+}
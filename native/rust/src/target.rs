@@ -0,0 +1,141 @@
+use std::{error::Error, fmt, sync::Mutex, thread, time::Duration};
+
+use crate::mapped_lib::MappedLib;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleError {
+    UnknownModule(String),
+    NotFound { module: &'static str, attempts: u32, reason: String },
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleError::UnknownModule(name) => write!(f, "unknown target module {:?}", name),
+            ModuleError::NotFound { module, attempts, reason } => write!(f, "{} not found after {} attempts: {}", module, attempts, reason),
+        }
+    }
+}
+
+impl Error for ModuleError {}
+
+// modules loaded after the hooks are installed are looked up again at the given interval
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub interval: Duration,
+}
+
+impl RetryPolicy {
+    pub const fn once() -> Self {
+        Self { attempts: 1, interval: Duration::ZERO }
+    }
+
+    pub const fn wait(attempts: u32, interval: Duration) -> Self {
+        Self { attempts, interval }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TargetModule {
+    pub name: &'static str,
+    // file names of the library, tried in order
+    pub file_names: &'static [&'static str],
+    pub retry: RetryPolicy,
+}
+
+impl TargetModule {
+    pub const fn new(name: &'static str, file_names: &'static [&'static str]) -> Self {
+        Self { name, file_names, retry: RetryPolicy::once() }
+    }
+
+    pub const fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+pub const CLIENT: TargetModule = TargetModule::new("client", &["libclient.so"]).retry(RetryPolicy::wait(5, Duration::from_millis(200)));
+pub const SCPLUGIN: TargetModule = TargetModule::new("scplugin", &["libscplugin.so"]).retry(RetryPolicy::wait(50, Duration::from_millis(200)));
+pub const ANDROID: TargetModule = TargetModule::new("android", &["libandroid.so"]);
+
+pub const TARGET_MODULES: &[&TargetModule] = &[&CLIENT, &SCPLUGIN, &ANDROID];
+
+// modules are mapped for the whole process lifetime, found modules are kept forever
+static FOUND_MODULES: Mutex<Vec<(&'static str, &'static MappedLib)>> = Mutex::new(Vec::new());
+
+fn search(target: &TargetModule) -> Result<MappedLib, String> {
+    let mut errors = Vec::new();
+
+    for file_name in target.file_names {
+        let mut mapped_lib = MappedLib::new(file_name.to_string());
+        match mapped_lib.search() {
+            Ok(_) => return Ok(mapped_lib),
+            Err(error) => errors.push(error.to_string()),
+        }
+    }
+
+    Err(errors.join(", "))
+}
+
+// waits for the module to be mapped according to its retry policy
+pub fn find_module(target: &TargetModule) -> Result<&'static MappedLib, ModuleError> {
+    if let Some((_, mapped_lib)) = FOUND_MODULES.lock().unwrap().iter().find(|(name, _)| *name == target.name) {
+        return Ok(mapped_lib);
+    }
+
+    let attempts = target.retry.attempts.max(1);
+    let mut reason = String::new();
+
+    for attempt in 1..=attempts {
+        match search(target) {
+            Ok(mapped_lib) => {
+                debug!("Found {} after {} attempts", target.name, attempt);
+                let mut found_modules = FOUND_MODULES.lock().unwrap();
+
+                // another thread may have found it in the meantime
+                if let Some((_, mapped_lib)) = found_modules.iter().find(|(name, _)| *name == target.name) {
+                    return Ok(mapped_lib);
+                }
+
+                let mapped_lib = &*Box::leak(Box::new(mapped_lib));
+                found_modules.push((target.name, mapped_lib));
+                return Ok(mapped_lib);
+            }
+            Err(error) => reason = error,
+        }
+
+        if attempt < attempts {
+            thread::sleep(target.retry.interval);
+        }
+    }
+
+    Err(ModuleError::NotFound { module: target.name, attempts, reason })
+}
+
+pub fn find_module_by_name(name: &str) -> Result<&'static MappedLib, ModuleError> {
+    let target = TARGET_MODULES.iter().find(|target| target.name == name).ok_or_else(|| ModuleError::UnknownModule(name.to_string()))?;
+    find_module(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_mapped_modules_once() {
+        let libc = TargetModule::new("libc", &["libc.so", "libc.so.6"]);
+        let first = find_module(&libc).unwrap();
+
+        assert!(std::ptr::eq(first, find_module(&libc).unwrap()));
+        assert!(!first.regions.is_empty());
+    }
+
+    #[test]
+    fn reports_missing_modules() {
+        let missing = TargetModule::new("missing", &["libmissing.so"]).retry(RetryPolicy::wait(2, Duration::from_millis(1)));
+
+        assert!(matches!(find_module(&missing), Err(ModuleError::NotFound { module: "missing", attempts: 2, .. })));
+        assert_eq!(find_module_by_name("unknown").unwrap_err(), ModuleError::UnknownModule("unknown".into()));
+    }
+}
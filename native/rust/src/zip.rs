@@ -0,0 +1,173 @@
+use std::{error::Error, fs::File, os::unix::fs::FileExt, path::Path};
+
+use crate::memory::{read_u16, read_u32};
+
+pub const ZIP_LOCAL_HEADER: u32 = 0x04034b50;
+pub const ZIP_CENTRAL_HEADER: u32 = 0x02014b50;
+pub const ZIP_END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+pub const ZIP_STORED: u16 = 0;
+
+// the end of central directory record is followed by a comment of at most 64K
+const END_OF_CENTRAL_DIRECTORY_SEARCH: u64 = 0x10000 + 22;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    // offset of the entry data in the archive
+    pub data_offset: u64,
+    pub size: u64,
+    pub stored: bool,
+}
+
+impl ZipEntry {
+    pub fn file_name(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+
+    pub fn contains_offset(&self, offset: u64) -> bool {
+        (self.data_offset..self.data_offset + self.size).contains(&offset)
+    }
+}
+
+// only the end of the archive, the central directory and the local headers are read, zip64 archives are not supported
+fn read_entries(archive_size: u64, read: impl Fn(u64, usize) -> Option<Vec<u8>>) -> Result<Vec<ZipEntry>, Box<dyn Error>> {
+    let tail_offset = archive_size.saturating_sub(END_OF_CENTRAL_DIRECTORY_SEARCH);
+    let tail = read(tail_offset, (archive_size - tail_offset) as usize).ok_or("Unable to read the end of the archive")?;
+
+    let end_of_directory = (0..tail.len().saturating_sub(21)).rev()
+        .find(|&offset| read_u32(&tail, offset) == Some(ZIP_END_OF_CENTRAL_DIRECTORY))
+        .ok_or("End of central directory not found")?;
+
+    let entry_count = read_u16(&tail, end_of_directory + 10).ok_or("Truncated end of central directory")? as usize;
+    let directory_size = read_u32(&tail, end_of_directory + 12).ok_or("Truncated end of central directory")?;
+    let directory_offset = read_u32(&tail, end_of_directory + 16).ok_or("Truncated end of central directory")?;
+
+    if directory_size == u32::MAX || directory_offset == u32::MAX {
+        return Err("Zip64 archives are not supported".into());
+    }
+
+    let directory = read(directory_offset as u64, directory_size as usize).ok_or("Truncated central directory")?;
+    let mut offset = 0;
+    let mut entries = Vec::with_capacity(entry_count);
+
+    for _ in 0..entry_count {
+        if read_u32(&directory, offset) != Some(ZIP_CENTRAL_HEADER) {
+            return Err(format!("Invalid central directory header at {:#x}", directory_offset as usize + offset).into());
+        }
+
+        let truncated = || format!("Truncated central directory header at {:#x}", directory_offset as usize + offset);
+        let method = read_u16(&directory, offset + 10).ok_or_else(truncated)?;
+        let size = read_u32(&directory, offset + 20).ok_or_else(truncated)?;
+        let name_length = read_u16(&directory, offset + 28).ok_or_else(truncated)? as usize;
+        let extra_length = read_u16(&directory, offset + 30).ok_or_else(truncated)? as usize;
+        let comment_length = read_u16(&directory, offset + 32).ok_or_else(truncated)? as usize;
+        let local_header = read_u32(&directory, offset + 42).ok_or_else(truncated)?;
+        let name = directory.get(offset + 46..offset + 46 + name_length).ok_or_else(truncated)?;
+
+        if size == u32::MAX || local_header == u32::MAX {
+            return Err("Zip64 archives are not supported".into());
+        }
+
+        let header = read(local_header as u64, 30).filter(|header| read_u32(header, 0) == Some(ZIP_LOCAL_HEADER))
+            .ok_or_else(|| format!("Invalid local header at {:#x}", local_header))?;
+        let local_name_length = read_u16(&header, 26).unwrap() as u64;
+        let local_extra_length = read_u16(&header, 28).unwrap() as u64;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).to_string(),
+            data_offset: local_header as u64 + 30 + local_name_length + local_extra_length,
+            size: size as u64,
+            stored: method == ZIP_STORED,
+        });
+
+        offset += 46 + name_length + extra_length + comment_length;
+    }
+
+    Ok(entries)
+}
+
+// lists the entries of a zip archive from its central directory
+pub fn zip_entries(archive: &[u8]) -> Result<Vec<ZipEntry>, Box<dyn Error>> {
+    read_entries(archive.len() as u64, |offset, length| {
+        archive.get(offset as usize..offset as usize + length).map(<[u8]>::to_vec)
+    })
+}
+
+// same as zip_entries without loading the whole archive, apks can weigh hundreds of megabytes
+pub fn zip_file_entries(path: &Path) -> Result<Vec<ZipEntry>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let archive_size = file.metadata()?.len();
+
+    read_entries(archive_size, |offset, length| {
+        let mut buffer = vec![0u8; length];
+        file.read_exact_at(&mut buffer, offset).ok().map(|_| buffer)
+    })
+}
+
+#[cfg(test)]
+pub(crate) fn stored_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+
+    for (name, data) in files {
+        let local_header = archive.len() as u32;
+        archive.extend(ZIP_LOCAL_HEADER.to_le_bytes());
+        archive.extend([0u8; 22]);
+        archive.extend((name.len() as u16).to_le_bytes());
+        archive.extend(4u16.to_le_bytes());
+        archive.extend(name.as_bytes());
+        archive.extend([0u8; 4]);
+        archive.extend(*data);
+
+        directory.extend(ZIP_CENTRAL_HEADER.to_le_bytes());
+        directory.extend([0u8; 6]);
+        directory.extend(ZIP_STORED.to_le_bytes());
+        directory.extend([0u8; 8]);
+        directory.extend((data.len() as u32).to_le_bytes());
+        directory.extend((data.len() as u32).to_le_bytes());
+        directory.extend((name.len() as u16).to_le_bytes());
+        directory.extend([0u8; 12]);
+        directory.extend(local_header.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    archive.extend(&directory);
+    archive.extend(ZIP_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    archive.extend([0u8; 4]);
+    archive.extend((files.len() as u16).to_le_bytes());
+    archive.extend((files.len() as u16).to_le_bytes());
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(directory_offset.to_le_bytes());
+    archive.extend([0u8; 2]);
+    archive
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_zip_entries() {
+        let archive = stored_zip(&[("AndroidManifest.xml", b"manifest"), ("lib/arm64-v8a/libclient.so", b"\x7fELF")]);
+        let entries = zip_entries(&archive).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].name, "lib/arm64-v8a/libclient.so");
+        assert_eq!(entries[1].file_name(), "libclient.so");
+        assert!(entries[1].stored);
+        assert_eq!(&archive[entries[1].data_offset as usize..][..entries[1].size as usize], b"\x7fELF");
+        assert!(zip_entries(b"not an archive").is_err());
+    }
+
+    #[test]
+    fn lists_zip_file_entries() {
+        let archive = stored_zip(&[("lib/arm64-v8a/libclient.so", b"\x7fELF")]);
+        let path = std::env::temp_dir().join(format!("zip_file_entries_{}.apk", std::process::id()));
+        std::fs::write(&path, &archive).unwrap();
+
+        let entries = zip_file_entries(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries.unwrap(), zip_entries(&archive).unwrap());
+    }
+}
@@ -9,4 +9,7 @@ data class NativeConfig(
     val composerHooks: Boolean = false,
     @JvmField
     val customEmojiFontPath: String? = null,
+    // milliseconds allowed to native signature scans, 0 disables the limit
+    @JvmField
+    val signatureScanBudget: Long = 10_000,
 )
\ No newline at end of file
@@ -7,7 +7,10 @@ import kotlin.random.Random
 
 class NativeLib {
     var nativeUnaryCallCallback: (NativeRequestData) -> Unit = {}
+    var signatureCacheCallback: (String) -> Unit = {}
     var signatureCache: String? = null
+    var signatureDefinitions: String? = null
+    var clientVersion: String? = null
 
     companion object {
         var initialized = false
@@ -22,7 +25,7 @@ class NativeLib {
             callback(this)
             preInit()
             return@runCatching {
-                signatureCache = init(signatureCache) ?: throw IllegalStateException("NativeLib init failed. Check logcat for more info")
+                signatureCache = init(signatureCache, signatureDefinitions, clientVersion) ?: throw IllegalStateException("NativeLib init failed. Check logcat for more info")
             }
         }.onFailure {
             initialized = false
@@ -42,6 +45,16 @@ class NativeLib {
         return null
     }
 
+    @Suppress("unused")
+    private fun onNativeSignatureCache(signatureCache: String) {
+        this.signatureCache = signatureCache
+        runCatching {
+            signatureCacheCallback(signatureCache)
+        }.onFailure {
+            Log.e("SnapEnhance", "signatureCacheCallback failed", it)
+        }
+    }
+
     fun loadNativeConfig(config: NativeConfig) {
         if (!initialized) return
         loadConfig(config)
@@ -67,7 +80,10 @@ class NativeLib {
     }
 
     private external fun preInit()
-    private external fun init(signatureCache: String?): String?
+    private external fun init(signatureCache: String?, signatureDefinitions: String?, clientVersion: String?): String?
+    external fun getSignatureReport(): String?
+    external fun getHookStatus(): String?
+    external fun getHooks(): String?
     private external fun loadConfig(config: NativeConfig)
     private external fun lockDatabase(name: String, callback: Runnable)
     external fun setComposerLoader(code: String)